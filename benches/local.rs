@@ -0,0 +1,51 @@
+//! Compares the overhead of atomic and single threaded tracking.
+//!
+//! The inner allocator hands out dangling pointers, so only the tracking is
+//! measured. Run with `cargo bench --bench local`.
+
+extern crate peakmem_alloc;
+
+use peakmem_alloc::{PeakAlloc, PeakAllocLocal, PeakAllocRelaxed};
+use std::alloc::{GlobalAlloc, Layout};
+use std::time::{Duration, Instant};
+
+const ITERATIONS: usize = 10_000_000;
+
+struct Dangling;
+
+unsafe impl GlobalAlloc for Dangling {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        layout.align() as *mut u8
+    }
+
+    unsafe fn dealloc(&self, _ptr: *mut u8, _layout: Layout) {}
+}
+
+fn churn<A: GlobalAlloc>(alloc: &A) -> Duration {
+    let layout = Layout::from_size_align(64, 8).unwrap();
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        unsafe {
+            let ptr = alloc.alloc(std::hint::black_box(layout));
+            alloc.dealloc(std::hint::black_box(ptr), layout);
+        }
+    }
+    start.elapsed()
+}
+
+fn report(name: &str, elapsed: Duration) {
+    let ops = (ITERATIONS * 2) as f64;
+    println!(
+        "{:<8} {:>8.2?} ({:.2} ns per operation)",
+        name,
+        elapsed,
+        elapsed.as_nanos() as f64 / ops
+    );
+}
+
+fn main() {
+    report("SeqCst", churn(&PeakAlloc::new(Dangling)));
+    report("Relaxed", churn(&PeakAllocRelaxed::relaxed(Dangling)));
+    // Safety: the benchmark only uses the allocator from this thread.
+    report("Local", churn(&unsafe { PeakAllocLocal::new(Dangling) }));
+}
@@ -0,0 +1,57 @@
+//! Compares the overhead of sequentially consistent and relaxed counters.
+//!
+//! The inner allocator hands out dangling pointers, so only the tracking is
+//! measured. Run with `cargo bench --bench ordering`.
+
+extern crate peakmem_alloc;
+
+use peakmem_alloc::{PeakAlloc, PeakAllocRelaxed};
+use std::alloc::{GlobalAlloc, Layout};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const THREADS: usize = 4;
+const ITERATIONS: usize = 1_000_000;
+
+struct Dangling;
+
+unsafe impl GlobalAlloc for Dangling {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        layout.align() as *mut u8
+    }
+
+    unsafe fn dealloc(&self, _ptr: *mut u8, _layout: Layout) {}
+}
+
+fn churn<A: GlobalAlloc + Sync>(alloc: &A) -> Duration {
+    let layout = Layout::from_size_align(64, 8).unwrap();
+    let start = Instant::now();
+    thread::scope(|s| {
+        for _ in 0..THREADS {
+            s.spawn(|| {
+                for _ in 0..ITERATIONS {
+                    unsafe {
+                        let ptr = alloc.alloc(layout);
+                        alloc.dealloc(ptr, layout);
+                    }
+                }
+            });
+        }
+    });
+    start.elapsed()
+}
+
+fn report(name: &str, elapsed: Duration) {
+    let ops = (THREADS * ITERATIONS * 2) as f64;
+    println!(
+        "{:<8} {:>8.2?} ({:.2} ns per operation)",
+        name,
+        elapsed,
+        elapsed.as_nanos() as f64 / ops
+    );
+}
+
+fn main() {
+    report("SeqCst", churn(&PeakAlloc::new(Dangling)));
+    report("Relaxed", churn(&PeakAllocRelaxed::relaxed(Dangling)));
+}
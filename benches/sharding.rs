@@ -0,0 +1,58 @@
+//! Compares the overhead of the global tracker and the sharded tracker when
+//! many threads allocate at the same time.
+//!
+//! The inner allocator hands out dangling pointers, so only the tracking is
+//! measured. Run with `cargo bench --bench sharding`.
+
+extern crate peakmem_alloc;
+
+use peakmem_alloc::PeakAlloc;
+use std::alloc::{GlobalAlloc, Layout};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const THREADS: usize = 8;
+const ITERATIONS: usize = 1_000_000;
+
+struct Dangling;
+
+unsafe impl GlobalAlloc for Dangling {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        layout.align() as *mut u8
+    }
+
+    unsafe fn dealloc(&self, _ptr: *mut u8, _layout: Layout) {}
+}
+
+fn churn<A: GlobalAlloc + Sync>(alloc: &A) -> Duration {
+    let layout = Layout::from_size_align(64, 8).unwrap();
+    let start = Instant::now();
+    thread::scope(|s| {
+        for _ in 0..THREADS {
+            s.spawn(|| {
+                for _ in 0..ITERATIONS {
+                    unsafe {
+                        let ptr = alloc.alloc(layout);
+                        alloc.dealloc(ptr, layout);
+                    }
+                }
+            });
+        }
+    });
+    start.elapsed()
+}
+
+fn report(name: &str, elapsed: Duration) {
+    let ops = (THREADS * ITERATIONS * 2) as f64;
+    println!(
+        "{:<8} {:>8.2?} ({:.2} ns per operation)",
+        name,
+        elapsed,
+        elapsed.as_nanos() as f64 / ops
+    );
+}
+
+fn main() {
+    report("Global", churn(&PeakAlloc::new(Dangling)));
+    report("Sharded", churn(&PeakAlloc::new(Dangling).with_sharding()));
+}
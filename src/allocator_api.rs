@@ -0,0 +1,149 @@
+//! Support for the unstable `Allocator` trait, which allows measuring the
+//! memory consumption of single collections, e.g. `Vec::new_in(&alloc)`.
+//! References get the implementation through the blanket impl of the standard
+//! library.
+
+use core::{
+    alloc::{AllocError, Allocator, Layout},
+    ptr::NonNull,
+};
+
+#[cfg(feature = "std")]
+use std::alloc::Global;
+
+use PeakAlloc;
+
+/// An instrumented instance of `Global`, the default allocator of the
+/// standard containers, for measuring single collections, e.g. with
+/// `Vec::new_in(&INSTRUMENTED_GLOBAL)`.
+///
+/// `Global` forwards to the registered global allocator and doesn't implement
+/// `GlobalAlloc`, so this can't be the global allocator itself, that's what
+/// [`INSTRUMENTED_SYSTEM`](crate::INSTRUMENTED_SYSTEM) is for.
+#[cfg(feature = "std")]
+#[cfg_attr(doc_cfg, doc(cfg(all(feature = "nightly", feature = "std"))))]
+pub static INSTRUMENTED_GLOBAL: PeakAlloc<Global> = PeakAlloc::global();
+
+#[cfg(feature = "std")]
+#[cfg_attr(doc_cfg, doc(cfg(all(feature = "nightly", feature = "std"))))]
+impl PeakAlloc<Global> {
+    /// Provides access to an instrumented instance of `Global`.
+    pub const fn global() -> Self {
+        PeakAlloc::new(Global)
+    }
+}
+
+#[cfg_attr(doc_cfg, doc(cfg(feature = "nightly")))]
+unsafe impl<T: Allocator, const RELAXED: bool> Allocator for PeakAlloc<T, RELAXED> {
+    #[inline]
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        if self.refuses(layout.size()) {
+            return Err(AllocError);
+        }
+        let block = self.inner.allocate(layout)?;
+        self.record_alloc(layout);
+        #[cfg(feature = "strict")]
+        self.strict_alloc(block.as_ptr() as *mut u8, layout.size());
+        Ok(block)
+    }
+
+    #[inline]
+    fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        if self.refuses(layout.size()) {
+            return Err(AllocError);
+        }
+        let block = self.inner.allocate_zeroed(layout)?;
+        self.record_alloc(layout);
+        self.record_zeroed(layout.size());
+        #[cfg(feature = "strict")]
+        self.strict_alloc(block.as_ptr() as *mut u8, layout.size());
+        Ok(block)
+    }
+
+    #[inline]
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.record_dealloc(layout);
+        #[cfg(feature = "strict")]
+        self.strict_dealloc(ptr.as_ptr(), layout.size());
+        self.inner.deallocate(ptr, layout)
+    }
+
+    #[inline]
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        if self.refuses(new_layout.size() - old_layout.size()) {
+            return Err(AllocError);
+        }
+        let block = self.inner.grow(ptr, old_layout, new_layout)?;
+        self.track_resize(old_layout, new_layout);
+        self.record_realloc(
+            ptr.as_ptr(),
+            block.as_ptr() as *mut u8,
+            old_layout.size().min(new_layout.size()),
+        );
+        #[cfg(feature = "strict")]
+        self.strict_realloc(
+            ptr.as_ptr(),
+            old_layout.size(),
+            block.as_ptr() as *mut u8,
+            new_layout.size(),
+        );
+        Ok(block)
+    }
+
+    #[inline]
+    unsafe fn grow_zeroed(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        if self.refuses(new_layout.size() - old_layout.size()) {
+            return Err(AllocError);
+        }
+        let block = self.inner.grow_zeroed(ptr, old_layout, new_layout)?;
+        self.track_resize(old_layout, new_layout);
+        self.record_zeroed(new_layout.size() - old_layout.size());
+        self.record_realloc(
+            ptr.as_ptr(),
+            block.as_ptr() as *mut u8,
+            old_layout.size().min(new_layout.size()),
+        );
+        #[cfg(feature = "strict")]
+        self.strict_realloc(
+            ptr.as_ptr(),
+            old_layout.size(),
+            block.as_ptr() as *mut u8,
+            new_layout.size(),
+        );
+        Ok(block)
+    }
+
+    #[inline]
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let block = self.inner.shrink(ptr, old_layout, new_layout)?;
+        self.track_resize(old_layout, new_layout);
+        self.record_realloc(
+            ptr.as_ptr(),
+            block.as_ptr() as *mut u8,
+            old_layout.size().min(new_layout.size()),
+        );
+        #[cfg(feature = "strict")]
+        self.strict_realloc(
+            ptr.as_ptr(),
+            old_layout.size(),
+            block.as_ptr() as *mut u8,
+            new_layout.size(),
+        );
+        Ok(block)
+    }
+}
@@ -0,0 +1,59 @@
+//! Capturing a backtrace of the largest allocation.
+
+use std::backtrace::Backtrace;
+use std::string::{String, ToString};
+use std::sync::{Mutex, PoisonError};
+
+use reentrancy;
+use PeakAlloc;
+
+/// The backtrace of the largest allocation.
+pub(crate) type LargestBacktrace = Mutex<Option<Backtrace>>;
+
+impl<T, const RELAXED: bool> PeakAlloc<T, RELAXED> {
+    /// Captures the backtrace of a new largest allocation.
+    pub(crate) fn capture_largest_backtrace(&self) {
+        // Capturing allocates, which must neither capture again nor run while
+        // the backtrace is locked on this thread.
+        reentrancy::guarded(|| {
+            let backtrace = Backtrace::force_capture();
+            let previous = self
+                .largest_backtrace
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .replace(backtrace);
+            drop(previous);
+        });
+    }
+
+    /// Returns the backtrace of the largest allocation, captured when it was
+    /// made.
+    ///
+    /// Capturing a backtrace is very slow and allocates, so this is meant for
+    /// debugging where a specific huge allocation comes from, not for
+    /// production. Returns `None` if no allocation was made since the last
+    /// reset, or when called from inside the allocator.
+    #[cfg_attr(doc_cfg, doc(cfg(feature = "backtrace")))]
+    pub fn largest_allocation_backtrace(&self) -> Option<String> {
+        reentrancy::guarded(|| {
+            self.largest_backtrace
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .as_ref()
+                .map(ToString::to_string)
+        })
+        .flatten()
+    }
+
+    /// Forgets the backtrace of the largest allocation.
+    #[cfg_attr(doc_cfg, doc(cfg(feature = "backtrace")))]
+    pub fn clear_largest_allocation_backtrace(&self) {
+        let previous = reentrancy::guarded(|| {
+            self.largest_backtrace
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .take()
+        });
+        drop(previous);
+    }
+}
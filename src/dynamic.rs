@@ -0,0 +1,72 @@
+//! Choosing the wrapped allocator at runtime.
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::fmt;
+use std::boxed::Box;
+
+/// A global allocator behind a trait object, to choose the allocator wrapped
+/// by a [`PeakAlloc`](crate::PeakAlloc) at runtime, e.g. the system allocator
+/// or an arena.
+///
+/// `GlobalAlloc` is not implemented for `Box<dyn GlobalAlloc>` by the standard
+/// library, so the box is wrapped in this type, which forwards every call.
+/// Boxing can't be done in a `const` context, so this can't be used for the
+/// global allocator, only for instances which measure single operations.
+///
+/// ```
+/// # extern crate peakmem_alloc;
+/// # use peakmem_alloc::{DynAlloc, PeakAlloc};
+/// # use std::alloc::{GlobalAlloc, Layout, System};
+/// # fn main() {
+/// let alloc = PeakAlloc::new(DynAlloc::new(System));
+/// let layout = Layout::from_size_align(64, 8).unwrap();
+/// unsafe {
+///     let ptr = alloc.alloc(layout);
+///     alloc.dealloc(ptr, layout);
+/// }
+/// # #[cfg(not(feature = "disabled"))]
+/// assert_eq!(alloc.get_peak_memory(), 64);
+/// # }
+/// ```
+pub struct DynAlloc(Box<dyn GlobalAlloc + Send + Sync>);
+
+impl DynAlloc {
+    /// Boxes the given allocator.
+    pub fn new<A: GlobalAlloc + Send + Sync + 'static>(alloc: A) -> Self {
+        DynAlloc(Box::new(alloc))
+    }
+}
+
+impl From<Box<dyn GlobalAlloc + Send + Sync>> for DynAlloc {
+    fn from(alloc: Box<dyn GlobalAlloc + Send + Sync>) -> Self {
+        DynAlloc(alloc)
+    }
+}
+
+impl fmt::Debug for DynAlloc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DynAlloc").finish_non_exhaustive()
+    }
+}
+
+unsafe impl GlobalAlloc for DynAlloc {
+    #[inline]
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.0.alloc(layout)
+    }
+
+    #[inline]
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.0.dealloc(ptr, layout)
+    }
+
+    #[inline]
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        self.0.alloc_zeroed(layout)
+    }
+
+    #[inline]
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        self.0.realloc(ptr, layout, new_size)
+    }
+}
@@ -0,0 +1,56 @@
+//! Reporting the peak memory consumption at process exit.
+
+use std::io::{self, Write};
+use std::os::raw::c_int;
+use std::sync::OnceLock;
+
+use {HumanBytes, PeakAlloc};
+
+extern "C" {
+    fn atexit(cb: extern "C" fn()) -> c_int;
+}
+
+trait PeakReport: Sync {
+    fn peak(&self) -> usize;
+}
+
+impl<T: Sync, const RELAXED: bool> PeakReport for PeakAlloc<T, RELAXED> {
+    fn peak(&self) -> usize {
+        self.get_peak_memory()
+    }
+}
+
+static REPORTED: OnceLock<&'static dyn PeakReport> = OnceLock::new();
+
+extern "C" fn report_peak() {
+    if let Some(alloc) = REPORTED.get() {
+        let peak = alloc.peak();
+        // Formatting into stderr doesn't allocate, and errors are ignored as
+        // nothing can be done about them during teardown.
+        let _ = writeln!(
+            io::stderr(),
+            "peak memory consumption: {} ({} bytes)",
+            HumanBytes(peak),
+            peak
+        );
+    }
+}
+
+#[cfg_attr(doc_cfg, doc(cfg(feature = "std")))]
+impl<T: Sync, const RELAXED: bool> PeakAlloc<T, RELAXED> {
+    /// Prints the peak memory consumption to stderr when the process exits.
+    ///
+    /// The report is printed by an `atexit` handler, so it runs when `main`
+    /// returns or `std::process::exit` is called, but not when the process is
+    /// aborted, e.g. by a panic with `panic = "abort"`, or killed by a signal.
+    /// Only the first call has an effect, subsequent calls are ignored.
+    pub fn print_peak_on_exit(&'static self) {
+        if REPORTED.set(self).is_ok() {
+            // Safety: `report_peak` is a valid handler, which only reads
+            // atomics and writes to stderr.
+            unsafe {
+                atexit(report_peak);
+            }
+        }
+    }
+}
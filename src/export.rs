@@ -0,0 +1,34 @@
+//! Integration with the `metrics` crate.
+
+use {PeakAlloc, Snapshot};
+
+#[cfg_attr(doc_cfg, doc(cfg(feature = "metrics")))]
+impl<T, const RELAXED: bool> PeakAlloc<T, RELAXED> {
+    /// Updates gauges of the `metrics` crate with the current metrics.
+    ///
+    /// The gauges are named `peakmem_alloc.peak_bytes`,
+    /// `peakmem_alloc.current_bytes`, `peakmem_alloc.total_allocated_bytes`,
+    /// `peakmem_alloc.largest_allocation_bytes`, `peakmem_alloc.allocations`
+    /// and `peakmem_alloc.deallocations`. The name given with
+    /// [`PeakAlloc::with_label`] is attached as a `label` label.
+    ///
+    /// Recorders allocate, so this must not be called from inside the
+    /// allocator, e.g. from a peak callback. Call it periodically from normal
+    /// code instead, e.g. from a background thread.
+    pub fn export_metrics(&self) {
+        self.export_snapshot(&self.snapshot());
+    }
+
+    pub(crate) fn export_snapshot(&self, snapshot: &Snapshot) {
+        let label = self.label.map(|label| ("label", label));
+        let labels = label.as_slice();
+        metrics::gauge!("peakmem_alloc.peak_bytes", labels).set(snapshot.peak as f64);
+        metrics::gauge!("peakmem_alloc.current_bytes", labels).set(snapshot.current as f64);
+        metrics::gauge!("peakmem_alloc.total_allocated_bytes", labels)
+            .set(snapshot.total_allocated as f64);
+        metrics::gauge!("peakmem_alloc.largest_allocation_bytes", labels)
+            .set(snapshot.largest_allocation as f64);
+        metrics::gauge!("peakmem_alloc.allocations", labels).set(snapshot.alloc_count as f64);
+        metrics::gauge!("peakmem_alloc.deallocations", labels).set(snapshot.dealloc_count as f64);
+    }
+}
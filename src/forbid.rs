@@ -0,0 +1,81 @@
+//! Aborting on allocations in code which must not allocate.
+
+use core::cell::Cell;
+use core::marker::PhantomData;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use std::io::{self, Write};
+use std::process;
+
+use PeakAlloc;
+
+thread_local! {
+    static FORBIDDEN: Cell<usize> = const { Cell::new(0) };
+}
+
+/// The number of guards alive on all threads, so the thread local is only read
+/// while there are any.
+static GUARDS: AtomicUsize = AtomicUsize::new(0);
+
+/// Aborts the process if allocations are forbidden on the current thread.
+#[inline]
+pub(crate) fn check(bytes: usize) {
+    if GUARDS.load(Ordering::Relaxed) == 0 {
+        return;
+    }
+    if FORBIDDEN.try_with(Cell::get).unwrap_or(0) != 0 {
+        violation(bytes);
+    }
+}
+
+#[cold]
+fn violation(bytes: usize) -> ! {
+    // Formatting an integer into stderr doesn't allocate, and errors are
+    // ignored as the process aborts anyway.
+    let _ = writeln!(
+        io::stderr(),
+        "allocation of {} bytes while allocations are forbidden",
+        bytes
+    );
+    process::abort()
+}
+
+/// Forbids allocations on the current thread until it is dropped, created by
+/// [`PeakAlloc::forbid_allocations`].
+#[derive(Debug)]
+pub struct ForbidGuard {
+    // The guard must be dropped on the thread which created it.
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Drop for ForbidGuard {
+    fn drop(&mut self) {
+        let _ = FORBIDDEN.try_with(|forbidden| forbidden.set(forbidden.get() - 1));
+        GUARDS.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg_attr(doc_cfg, doc(cfg(feature = "std")))]
+impl<T, const RELAXED: bool> PeakAlloc<T, RELAXED> {
+    /// Aborts the process on any allocation of the current thread through
+    /// this allocator, or growing reallocation, until the returned guard is
+    /// dropped. Other threads may keep allocating.
+    ///
+    /// This asserts that a code path is allocation free. The process aborts
+    /// after printing the requested size to stderr, instead of panicking, as
+    /// a global allocator must not unwind, and the panic would allocate
+    /// itself. For a growing reallocation, the size is the number of bytes
+    /// added. To find the offending allocation, run the code under a debugger,
+    /// which stops at the abort.
+    ///
+    /// Guards can be nested, allocations are allowed again once all of them
+    /// are dropped. Like [`PeakAlloc::suspend`], the state is kept in a thread
+    /// local, so it applies to all instances. Deallocations are always
+    /// allowed.
+    pub fn forbid_allocations(&self) -> ForbidGuard {
+        GUARDS.fetch_add(1, Ordering::Relaxed);
+        let _ = FORBIDDEN.try_with(|forbidden| forbidden.set(forbidden.get() + 1));
+        ForbidGuard {
+            _not_send: PhantomData,
+        }
+    }
+}
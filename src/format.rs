@@ -0,0 +1,103 @@
+use core::fmt;
+
+use {PeakAlloc, Snapshot};
+
+const UNITS: [&str; 6] = ["KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+
+/// Displays a number of bytes with a binary prefix, e.g. `1.50 MiB`.
+///
+/// Values below 1 KiB are displayed as plain bytes, e.g. `512 B`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HumanBytes(pub usize);
+
+impl fmt::Display for HumanBytes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0 < 1024 {
+            return write!(f, "{} B", self.0);
+        }
+        let mut value = self.0 as f64 / 1024.0;
+        let mut unit = 0;
+        while value >= 1024.0 && unit + 1 < UNITS.len() {
+            value /= 1024.0;
+            unit += 1;
+        }
+        write!(f, "{:.2} {}", value, UNITS[unit])
+    }
+}
+
+/// A unit to convert a number of bytes to, see
+/// [`PeakAlloc::get_peak_in`](crate::PeakAlloc::get_peak_in).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteUnit {
+    /// Bytes
+    B,
+    /// Kibibytes, 1024 bytes
+    KiB,
+    /// Mebibytes, 1024 KiB
+    MiB,
+    /// Gibibytes, 1024 MiB
+    GiB,
+}
+
+impl ByteUnit {
+    /// Returns the number of bytes in one unit.
+    pub const fn bytes(self) -> usize {
+        match self {
+            ByteUnit::B => 1,
+            ByteUnit::KiB => 1 << 10,
+            ByteUnit::MiB => 1 << 20,
+            ByteUnit::GiB => 1 << 30,
+        }
+    }
+
+    /// Converts a number of bytes to this unit.
+    pub fn convert(self, bytes: usize) -> f64 {
+        bytes as f64 / self.bytes() as f64
+    }
+}
+
+/// Formats a number of bytes with a binary prefix, e.g. `1.50 MiB`.
+#[cfg(feature = "std")]
+#[cfg_attr(doc_cfg, doc(cfg(feature = "std")))]
+pub fn format_bytes(bytes: usize) -> String {
+    HumanBytes(bytes).to_string()
+}
+
+impl fmt::Display for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "peak: {}, current: {}, total allocated: {}, allocations: {}, \
+             deallocations: {}, largest allocation: {}, zeroed: {}",
+            HumanBytes(self.peak),
+            HumanBytes(self.current),
+            HumanBytes(self.total_allocated),
+            self.alloc_count,
+            self.dealloc_count,
+            HumanBytes(self.largest_allocation),
+            HumanBytes(self.zeroed_bytes),
+        )
+    }
+}
+
+impl<T: fmt::Debug, const RELAXED: bool> fmt::Debug for PeakAlloc<T, RELAXED> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let snapshot = self.snapshot();
+        let mut debug = f.debug_struct("PeakAlloc");
+        if let Some(label) = self.label {
+            debug.field("label", &label);
+        }
+        debug
+            .field("peak", &snapshot.peak)
+            .field("current", &snapshot.current)
+            .field("total_allocated", &snapshot.total_allocated)
+            .field("alloc_count", &snapshot.alloc_count)
+            .field("dealloc_count", &snapshot.dealloc_count)
+            .field("largest_allocation", &snapshot.largest_allocation)
+            .field("zeroed_bytes", &snapshot.zeroed_bytes)
+            .field("realloc_moved_count", &self.get_realloc_moved_count())
+            .field("realloc_inplace_count", &self.get_realloc_inplace_count())
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
@@ -0,0 +1,108 @@
+use core::ops::Range;
+
+/// The number of buckets of the size histogram, i.e. the length of the array
+/// returned by [`PeakAlloc::size_histogram`](crate::PeakAlloc::size_histogram).
+pub const HISTOGRAM_BUCKETS: usize = 32;
+
+/// The number of buckets of the alignment histogram, one per power of two,
+/// i.e. the length of the array returned by
+/// [`PeakAlloc::alignment_histogram`](crate::PeakAlloc::alignment_histogram).
+pub const ALIGNMENT_BUCKETS: usize = usize::BITS as usize;
+
+/// Get the histogram bucket of an allocation of `bytes`.
+///
+/// Bucket `i` holds the sizes in `(2^(i-1), 2^i]`, i.e. sizes are rounded up
+/// to the next power of two. Bucket 0 holds the sizes 0 and 1, the last bucket
+/// also holds all sizes which are too large for the other buckets.
+#[inline]
+pub(crate) fn bucket(bytes: usize) -> usize {
+    // `bytes - 1` would underflow for zero sized allocations.
+    if bytes <= 1 {
+        return 0;
+    }
+    let rounded_log2 = (usize::BITS - (bytes - 1).leading_zeros()) as usize;
+    rounded_log2.min(HISTOGRAM_BUCKETS - 1)
+}
+
+/// Get the sizes held by histogram bucket `index`, see [`bucket`].
+///
+/// The last bucket ends at `usize::MAX`, which it holds as well.
+pub(crate) fn bucket_range(index: usize) -> Range<usize> {
+    if index == 0 {
+        return 0..2;
+    }
+    let start = (1 << (index - 1)) + 1;
+    if index == HISTOGRAM_BUCKETS - 1 {
+        return start..usize::MAX;
+    }
+    start..(1 << index) + 1
+}
+
+/// Approximate statistics of the allocation sizes, computed by
+/// [`PeakAlloc::histogram_summary`](crate::PeakAlloc::histogram_summary).
+///
+/// The histogram only knows sizes rounded up to the next power of two, so all
+/// sizes are upper bounds, which are off by less than a factor of 2. Sizes in
+/// the last bucket are counted as its lower bound.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HistogramSummary {
+    /// The number of allocations
+    pub count: usize,
+    /// The mean allocation size
+    pub mean: f64,
+    /// The median allocation size
+    pub median: usize,
+    /// The 90th percentile of the allocation sizes
+    pub p90: usize,
+    /// The 99th percentile of the allocation sizes
+    pub p99: usize,
+}
+
+impl HistogramSummary {
+    pub(crate) fn new(histogram: &[usize; HISTOGRAM_BUCKETS]) -> Self {
+        let count: usize = histogram.iter().sum();
+        let bytes: f64 = histogram
+            .iter()
+            .enumerate()
+            .map(|(index, &n)| n as f64 * bucket_size(index) as f64)
+            .sum();
+        HistogramSummary {
+            count,
+            mean: if count == 0 {
+                0.0
+            } else {
+                bytes / count as f64
+            },
+            median: percentile(histogram, count, 50),
+            p90: percentile(histogram, count, 90),
+            p99: percentile(histogram, count, 99),
+        }
+    }
+}
+
+/// The size an allocation in bucket `index` is counted as.
+fn bucket_size(index: usize) -> usize {
+    if index == HISTOGRAM_BUCKETS - 1 {
+        bucket_range(index).start
+    } else {
+        bucket_range(index).end - 1
+    }
+}
+
+/// The size below or at which `percent` of the `count` allocations are.
+fn percentile(histogram: &[usize; HISTOGRAM_BUCKETS], count: usize, percent: usize) -> usize {
+    if count == 0 {
+        return 0;
+    }
+    // The rank of the allocation, rounded up, so the percentile of a single
+    // allocation is its size. Split up to not overflow for large counts.
+    let rank = count / 100 * percent + (count % 100 * percent).div_ceil(100);
+    let mut seen = 0;
+    for (index, &n) in histogram.iter().enumerate() {
+        seen += n;
+        if seen >= rank {
+            return bucket_size(index);
+        }
+    }
+    bucket_size(HISTOGRAM_BUCKETS - 1)
+}
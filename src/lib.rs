@@ -6,21 +6,86 @@
 //! ```
 //! extern crate peakmem_alloc;
 //!
+//! # #[cfg(feature = "std")]
 //! use peakmem_alloc::{PeakAlloc, INSTRUMENTED_SYSTEM};
+//! # #[cfg(feature = "std")]
 //! use std::alloc::System;
 //!
+//! # #[cfg(feature = "std")]
 //! #[global_allocator]
 //! static GLOBAL: &PeakAlloc<System> = &INSTRUMENTED_SYSTEM;
 //!
 //! fn main() {
+//! # #[cfg(feature = "std")] {
 //!    GLOBAL.reset_peak_memory();
 //!    let _x: Vec<u8> = Vec::with_capacity(1_024);
 //!    println!(
 //!        "Peak Memory used by function : {:#?}",
 //!        GLOBAL.get_peak_memory()
 //!    );
+//! # }
 //! }
 //! ```
+//!
+//! ## Reading from inside the allocator
+//!
+//! All getters, e.g. [`PeakAlloc::get_current_memory`],
+//! [`PeakAlloc::get_peak_memory`] and [`PeakAlloc::snapshot`], as well as
+//! scopes and checkpoints, only read and write atomics. They never allocate
+//! and never call into the wrapped allocator, so they can be used from peak
+//! callbacks, allocator hooks or while holding a lock of the allocator. Only
+//! the named regions, the sampler, the `metrics` export and `format_bytes`
+//! allocate.
+//!
+//! ## Signal safety
+//!
+//! The getters of the metrics, i.e. the `get_*` methods,
+//! [`PeakAlloc::peak_and_current`], [`PeakAlloc::snapshot`] and
+//! [`PeakAlloc::clone_stats`], are async-signal-safe: they only load atomics,
+//! which are lock-free on all targets this crate compiles for, never take a
+//! lock, never allocate and never panic. They can be called from a signal
+//! handler, e.g. by a sampling profiler, even if the signal interrupted an
+//! allocation on the same thread. The values may then not include that
+//! allocation yet. This is part of the API contract, features which need
+//! locks keep them out of these reads.
+//!
+//! ## Features
+//!
+//! - `std` (default): Provides [`INSTRUMENTED_SYSTEM`] and everything else
+//!   which requires the standard library. Without it the crate is `no_std`,
+//!   and can wrap any allocator implementing `GlobalAlloc`.
+//! - `backtrace`: Captures a backtrace of the largest allocation, see
+//!   [`PeakAlloc::largest_allocation_backtrace`]. This slows down every
+//!   allocation which is larger than all previous ones considerably, and is
+//!   only meant for debugging.
+//! - `disabled`: Turns the allocator into a plain passthrough to the wrapped
+//!   allocator, without any atomic operations. All accessors keep compiling and
+//!   report 0, so the same code can be used with and without instrumentation.
+//!   Limits, budgets and `forbid` are not enforced either.
+//! - `metrics`: Exports the metrics as gauges of the `metrics` crate, see
+//!   [`PeakAlloc::export_metrics`].
+//! - `nightly`: Implements the unstable `Allocator` trait, and provides
+//!   [`INSTRUMENTED_GLOBAL`] to measure single collections.
+//! - `peak_timestamp`: Records when the peak was reached, see
+//!   [`PeakAlloc::peak_timestamp`].
+//! - `prometheus`: Renders the metrics in the Prometheus text format, see
+//!   [`PeakAlloc::render_prometheus`].
+//! - `serde`: Implements `Serialize` and `Deserialize` for [`Snapshot`].
+//! - `stats_alloc_compat`: Provides the counters with the field names of the
+//!   `stats_alloc` crate, see [`PeakAlloc::as_region_stats`]. This doesn't
+//!   depend on `stats_alloc`.
+//! - `strict`: Records every allocation in use to detect leaks and
+//!   deallocations with the wrong size, see
+//!   [`PeakAlloc::outstanding_allocations`]. This takes a global lock on
+//!   every allocation and is only meant for tests.
+//! - `testing`: Adds assertions for memory regression tests, see
+//!   [`PeakAlloc::assert_peak_below`].
+//! - `tokio`: Keeps the baseline of [`PeakAlloc::measure_tokio_task`] in a
+//!   task-local, see [`PeakAlloc::task_memory`].
+//! - `tracing`: Emits a `tracing` event whenever the peak grows, see
+//!   [`PeakAlloc::set_peak_threshold`]. The event is emitted from inside the
+//!   allocator, so the subscriber must not deadlock when allocating itself, and
+//!   events caused by the subscriber's own allocations are dropped.
 
 #![deny(
     missing_debug_implementations,
@@ -32,80 +97,1638 @@
     unused_qualifications,
     missing_docs
 )]
-#![cfg_attr(doc_cfg, feature(allocator_api))]
+#![cfg_attr(not(feature = "std"), no_std)]
+#![cfg_attr(any(doc_cfg, feature = "nightly"), feature(allocator_api))]
 #![cfg_attr(doc_cfg, feature(doc_cfg))]
 
-use std::{
-    alloc::{GlobalAlloc, Layout, System},
-    sync::atomic::{AtomicIsize, AtomicUsize, Ordering},
+#[cfg(feature = "std")]
+extern crate core;
+
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    mem,
+    ops::Range,
+    ptr,
+    sync::atomic::{AtomicBool, AtomicIsize, AtomicPtr, AtomicUsize, Ordering},
 };
+#[cfg(feature = "std")]
+use std::alloc::System;
+
+#[cfg(feature = "metrics")]
+extern crate metrics;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(feature = "tokio")]
+extern crate tokio;
+#[cfg(feature = "tracing")]
+extern crate tracing;
+
+#[macro_use]
+mod macros;
+
+#[cfg(feature = "nightly")]
+mod allocator_api;
+#[cfg(feature = "backtrace")]
+mod backtrace;
+#[cfg(feature = "std")]
+mod dynamic;
+#[cfg(all(feature = "std", any(unix, windows)))]
+mod exit;
+#[cfg(feature = "metrics")]
+mod export;
+#[cfg(feature = "std")]
+mod forbid;
+mod format;
+mod histogram;
+mod local;
+#[cfg(feature = "std")]
+mod mock;
+#[cfg(feature = "prometheus")]
+mod prometheus;
+#[cfg(feature = "std")]
+mod publish;
+#[cfg(feature = "std")]
+mod reentrancy;
+#[cfg(feature = "std")]
+mod region;
+#[cfg(feature = "std")]
+mod sampler;
+mod scope;
+mod shard;
+mod stats;
+#[cfg(feature = "stats_alloc_compat")]
+mod stats_alloc;
+#[cfg(feature = "strict")]
+mod strict;
+#[cfg(feature = "std")]
+mod suspend;
+mod task;
+#[cfg(feature = "testing")]
+mod testing;
+#[cfg(feature = "std")]
+mod thread;
+#[cfg(feature = "peak_timestamp")]
+mod timestamp;
+
+#[cfg(all(feature = "nightly", feature = "std"))]
+pub use allocator_api::INSTRUMENTED_GLOBAL;
+#[cfg(feature = "std")]
+#[cfg_attr(doc_cfg, doc(cfg(feature = "std")))]
+pub use dynamic::DynAlloc;
+#[cfg(feature = "std")]
+#[cfg_attr(doc_cfg, doc(cfg(feature = "std")))]
+pub use forbid::ForbidGuard;
+#[cfg(feature = "std")]
+pub use format::format_bytes;
+pub use format::{ByteUnit, HumanBytes};
+pub use histogram::{HistogramSummary, ALIGNMENT_BUCKETS, HISTOGRAM_BUCKETS};
+pub use local::PeakAllocLocal;
+#[cfg(feature = "std")]
+#[cfg_attr(doc_cfg, doc(cfg(feature = "std")))]
+pub use mock::CountingMock;
+#[cfg(feature = "std")]
+#[cfg_attr(doc_cfg, doc(cfg(feature = "std")))]
+pub use sampler::{write_csv, SamplerHandle};
+pub use scope::{PeakGuard, SavedState};
+pub use shard::{SHARDS, SHARD_FLUSH_THRESHOLD};
+pub use stats::{PeakStats, SnapshotDelta};
+#[cfg(feature = "stats_alloc_compat")]
+#[cfg_attr(doc_cfg, doc(cfg(feature = "stats_alloc_compat")))]
+pub use stats_alloc::RegionStats;
+#[cfg(feature = "std")]
+#[cfg_attr(doc_cfg, doc(cfg(feature = "std")))]
+pub use suspend::SuspendGuard;
+pub use task::MeasureTask;
+#[cfg(feature = "std")]
+#[cfg_attr(doc_cfg, doc(cfg(feature = "std")))]
+pub use thread::ThreadPeakAlloc;
+
+/// The counter of the total allocated bytes, which is 64 bit wide if possible.
+#[cfg(target_has_atomic = "64")]
+type TotalCounter = core::sync::atomic::AtomicU64;
+#[cfg(not(target_has_atomic = "64"))]
+type TotalCounter = AtomicUsize;
+
+/// The number of sizes returned by [`PeakAlloc::top_allocations`].
+pub const TOP_ALLOCATIONS: usize = 8;
+
+/// The valley before any deallocation.
+const NO_VALLEY: usize = usize::MAX;
+
+/// The baseline before it is captured.
+const NO_BASELINE: usize = usize::MAX;
+use shard::Shard;
 
 /// An allocator middleware which keeps track of peak memory consumption.
-#[derive(Default, Debug)]
-pub struct PeakAlloc<T: GlobalAlloc> {
+///
+/// All counters are updated with sequentially consistent atomic operations by
+/// default. Setting `RELAXED` to `true`, see [`PeakAllocRelaxed`], uses relaxed
+/// operations instead.
+///
+/// The allocator can be configured with the const `with_*` methods before it
+/// goes live. As the global allocator has to be initialized in a `static`,
+/// only these const methods are available there, e.g. values read from the
+/// environment have to be applied with the `set_*` methods early in `main`:
+///
+/// ```
+/// # extern crate peakmem_alloc;
+/// # use peakmem_alloc::PeakAlloc;
+/// # use std::alloc::System;
+/// fn on_peak(_peak: usize) {}
+///
+/// #[global_allocator]
+/// static GLOBAL: PeakAlloc<System> = PeakAlloc::new(System)
+///     .with_limit(1 << 30)
+///     .with_peak_threshold(1 << 20)
+///     .with_peak_callback(on_peak);
+/// # fn main() {}
+/// ```
+pub struct PeakAlloc<T, const RELAXED: bool = false> {
     peak_bytes_allocated_tracker: AtomicIsize,
     peak_bytes_allocated: AtomicUsize,
+    #[cfg(feature = "peak_timestamp")]
+    peak_timestamp: timestamp::PeakTimestamp,
+    min_bytes_allocated: AtomicUsize,
+    baseline: AtomicUsize,
+    total_bytes_allocated: TotalCounter,
+    total_bytes_deallocated: TotalCounter,
+    initial_alloc_bytes: AtomicUsize,
+    realloc_growth_bytes: AtomicUsize,
+    realloc_shrink_bytes: AtomicUsize,
+    zeroed_bytes_allocated: AtomicUsize,
+    alloc_count: AtomicUsize,
+    dealloc_count: AtomicUsize,
+    realloc_moved_count: AtomicUsize,
+    realloc_inplace_count: AtomicUsize,
+    realloc_copied_bytes: AtomicUsize,
+    largest_allocation: AtomicUsize,
+    top_allocations: [AtomicUsize; TOP_ALLOCATIONS],
+    top_allocations_lock: AtomicBool,
+    #[cfg(feature = "backtrace")]
+    largest_backtrace: backtrace::LargestBacktrace,
+    size_histogram: [AtomicUsize; HISTOGRAM_BUCKETS],
+    size_class_current: [AtomicUsize; HISTOGRAM_BUCKETS],
+    size_class_peak: [AtomicUsize; HISTOGRAM_BUCKETS],
+    track_size_classes: bool,
+    alignment_histogram: [AtomicUsize; ALIGNMENT_BUCKETS],
+    track_alignment: bool,
+    alignment_overhead: AtomicUsize,
+    limit: AtomicUsize,
+    limit_hit: AtomicBool,
+    min_tracked_size: AtomicUsize,
+    scope_peak: AtomicUsize,
+    scope_excluded: AtomicUsize,
+    scope_excluded_level: AtomicUsize,
+    peak_callback: AtomicPtr<()>,
+    peak_threshold: AtomicUsize,
+    last_notified_peak: AtomicUsize,
+    log_interval: AtomicUsize,
+    last_logged_peak: AtomicUsize,
+    peak_log_pending: AtomicBool,
+    last_alloc_was_peak: AtomicBool,
+    budget: AtomicUsize,
+    budget_callback: AtomicPtr<()>,
+    sharded: bool,
+    shards: [Shard; SHARDS],
+    #[cfg(feature = "std")]
+    region_peaks: region::RegionPeaks,
+    #[cfg(feature = "std")]
+    peak_observer: publish::PeakObserver,
+    #[cfg(feature = "std")]
+    last_observed_peak: AtomicUsize,
+    #[cfg(feature = "strict")]
+    outstanding: strict::Outstanding,
+    label: Option<&'static str>,
     inner: T,
 }
 
+/// A [`PeakAlloc`] which updates its counters with relaxed atomic operations.
+///
+/// This avoids memory fences on every allocation, which is noticeable in
+/// allocation heavy benchmarks on weakly ordered architectures. Each counter is
+/// still updated atomically, but reads of different counters, e.g. in
+/// [`PeakAlloc::snapshot`], may observe the updates of other threads in a
+/// different order.
+pub type PeakAllocRelaxed<T> = PeakAlloc<T, true>;
+
+/// A copy of all the metrics of a [`PeakAlloc`], taken by
+/// [`PeakAlloc::snapshot`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Snapshot {
+    /// The peak memory consumption
+    pub peak: usize,
+    /// The memory allocated at the time of the snapshot
+    pub current: usize,
+    /// The total number of bytes allocated
+    pub total_allocated: usize,
+    /// The number of allocations
+    pub alloc_count: usize,
+    /// The number of deallocations
+    pub dealloc_count: usize,
+    /// The size of the largest allocation
+    pub largest_allocation: usize,
+    /// The number of bytes allocated already zeroed
+    pub zeroed_bytes: usize,
+}
+
+/// A marked point in time, taken by [`PeakAlloc::checkpoint`].
+///
+/// Unlike [`PeakGuard`], checkpoints don't need to be nested, so any number of
+/// overlapping regions can be observed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Checkpoint {
+    /// The peak memory consumption at the checkpoint
+    pub peak: usize,
+    /// The memory allocated at the checkpoint
+    pub current: usize,
+}
+
+/// What to do with an allocation exceeding the budget, decided by the
+/// callback registered with [`PeakAlloc::set_budget_callback`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Admission {
+    /// Allocate anyway.
+    Continue,
+    /// Fail the allocation by returning a null pointer.
+    Fail,
+    /// Wait until enough memory is freed, then consult the callback again.
+    Wait,
+}
+
 /// An instrumented instance of the system allocator.
-pub static INSTRUMENTED_SYSTEM: PeakAlloc<System> = PeakAlloc {
-    peak_bytes_allocated_tracker: AtomicIsize::new(0),
-    peak_bytes_allocated: AtomicUsize::new(0),
-    inner: System,
-};
+///
+/// Registering it requires a reference, as in the crate example. Declaring
+/// the allocator directly works as well, as [`PeakAlloc::system`] is `const`:
+///
+/// ```
+/// # extern crate peakmem_alloc;
+/// # use peakmem_alloc::PeakAlloc;
+/// # use std::alloc::System;
+/// #[global_allocator]
+/// static GLOBAL: PeakAlloc<System> = PeakAlloc::system();
+/// # fn main() {}
+/// ```
+#[cfg(feature = "std")]
+#[cfg_attr(doc_cfg, doc(cfg(feature = "std")))]
+pub static INSTRUMENTED_SYSTEM: PeakAlloc<System> = PeakAlloc::system();
 
+#[cfg(feature = "std")]
+#[cfg_attr(doc_cfg, doc(cfg(feature = "std")))]
 impl PeakAlloc<System> {
     /// Provides access to an instrumented instance of the system allocator.
     pub const fn system() -> Self {
-        PeakAlloc {
-            peak_bytes_allocated_tracker: AtomicIsize::new(0),
-            peak_bytes_allocated: AtomicUsize::new(0),
-            inner: System,
-        }
+        PeakAlloc::new(System)
     }
 }
 
-impl<T: GlobalAlloc> PeakAlloc<T> {
+/// Only available if the wrapped allocator implements `Default`, other
+/// allocators are wrapped with [`PeakAlloc::new`].
+impl<T: Default, const RELAXED: bool> Default for PeakAlloc<T, RELAXED> {
+    fn default() -> Self {
+        PeakAlloc::with_inner(T::default())
+    }
+}
+
+impl<T> PeakAlloc<T> {
     /// Provides access to an instrumented instance of the given global
     /// allocator.
+    ///
+    /// The inner allocator can be another middleware, including a
+    /// `PeakAlloc` or a reference to one. Each layer tracks the memory passing
+    /// through it independently.
     pub const fn new(inner: T) -> Self {
+        PeakAlloc::with_inner(inner)
+    }
+}
+
+impl<T> PeakAllocRelaxed<T> {
+    /// Provides access to an instrumented instance of the given global
+    /// allocator, which uses relaxed atomic operations.
+    pub const fn relaxed(inner: T) -> Self {
+        PeakAlloc::with_inner(inner)
+    }
+}
+
+impl<T, const RELAXED: bool> PeakAlloc<T, RELAXED> {
+    const ORDERING: Ordering = if RELAXED {
+        Ordering::Relaxed
+    } else {
+        Ordering::SeqCst
+    };
+
+    const fn with_inner(inner: T) -> Self {
         PeakAlloc {
             peak_bytes_allocated_tracker: AtomicIsize::new(0),
             peak_bytes_allocated: AtomicUsize::new(0),
+            #[cfg(feature = "peak_timestamp")]
+            peak_timestamp: timestamp::PeakTimestamp::new(0),
+            min_bytes_allocated: AtomicUsize::new(NO_VALLEY),
+            baseline: AtomicUsize::new(NO_BASELINE),
+            total_bytes_allocated: TotalCounter::new(0),
+            total_bytes_deallocated: TotalCounter::new(0),
+            initial_alloc_bytes: AtomicUsize::new(0),
+            realloc_growth_bytes: AtomicUsize::new(0),
+            realloc_shrink_bytes: AtomicUsize::new(0),
+            zeroed_bytes_allocated: AtomicUsize::new(0),
+            alloc_count: AtomicUsize::new(0),
+            dealloc_count: AtomicUsize::new(0),
+            realloc_moved_count: AtomicUsize::new(0),
+            realloc_inplace_count: AtomicUsize::new(0),
+            realloc_copied_bytes: AtomicUsize::new(0),
+            largest_allocation: AtomicUsize::new(0),
+            top_allocations: [const { AtomicUsize::new(0) }; TOP_ALLOCATIONS],
+            top_allocations_lock: AtomicBool::new(false),
+            #[cfg(feature = "backtrace")]
+            largest_backtrace: std::sync::Mutex::new(None),
+            size_histogram: [const { AtomicUsize::new(0) }; HISTOGRAM_BUCKETS],
+            size_class_current: [const { AtomicUsize::new(0) }; HISTOGRAM_BUCKETS],
+            size_class_peak: [const { AtomicUsize::new(0) }; HISTOGRAM_BUCKETS],
+            track_size_classes: false,
+            alignment_histogram: [const { AtomicUsize::new(0) }; ALIGNMENT_BUCKETS],
+            track_alignment: false,
+            alignment_overhead: AtomicUsize::new(0),
+            limit: AtomicUsize::new(0),
+            limit_hit: AtomicBool::new(false),
+            min_tracked_size: AtomicUsize::new(0),
+            scope_peak: AtomicUsize::new(0),
+            scope_excluded: AtomicUsize::new(0),
+            scope_excluded_level: AtomicUsize::new(0),
+            peak_callback: AtomicPtr::new(ptr::null_mut()),
+            peak_threshold: AtomicUsize::new(0),
+            last_notified_peak: AtomicUsize::new(0),
+            log_interval: AtomicUsize::new(0),
+            last_logged_peak: AtomicUsize::new(0),
+            peak_log_pending: AtomicBool::new(false),
+            last_alloc_was_peak: AtomicBool::new(false),
+            budget: AtomicUsize::new(0),
+            budget_callback: AtomicPtr::new(ptr::null_mut()),
+            sharded: false,
+            shards: [const { Shard::new() }; SHARDS],
+            #[cfg(feature = "std")]
+            region_peaks: std::sync::Mutex::new(std::collections::BTreeMap::new()),
+            #[cfg(feature = "std")]
+            peak_observer: std::sync::Mutex::new(None),
+            #[cfg(feature = "std")]
+            last_observed_peak: AtomicUsize::new(0),
+            #[cfg(feature = "strict")]
+            outstanding: strict::Outstanding::new(),
+            label: None,
             inner,
         }
     }
 
-    /// Resets the peak memory to 0
+    /// Buffers changes of the memory in use in counters shared by fewer
+    /// threads, to reduce contention in multithreaded programs.
+    ///
+    /// Each thread picks one of a fixed number of shards and only hands its
+    /// buffered changes to the global tracker once they exceed
+    /// [`SHARD_FLUSH_THRESHOLD`] bytes. As a consequence the current memory,
+    /// the peak and scoped peaks are only approximate, each shard may hold
+    /// back up to that many bytes.
+    ///
+    /// Only the memory in use is sharded. The counters, the histograms and the
+    /// largest allocations are still updated in atomics shared by all threads
+    /// on every allocation, so they stay exact, but keep contending. Sharding
+    /// only removes the contention on the tracker and the peak.
+    pub const fn with_sharding(mut self) -> Self {
+        self.sharded = true;
+        self
+    }
+
+    /// Estimates the memory wasted by padding allocations to their alignment.
+    ///
+    /// `Layout::size` doesn't include the cost of satisfying large alignments,
+    /// which the inner allocator may have to pay. With this enabled, the size
+    /// of every allocation in use is rounded up to a multiple of its alignment,
+    /// and the sum of the difference is reported by
+    /// [`get_alignment_overhead`](Self::get_alignment_overhead). This is only
+    /// an approximation of what the inner allocator actually does. It also
+    /// enables the [`PeakAlloc::alignment_histogram`].
+    pub const fn with_alignment_tracking(mut self) -> Self {
+        self.track_alignment = true;
+        self
+    }
+
+    /// Tracks the peak memory consumption of each size class, see
+    /// [`PeakAlloc::peak_by_size_class`].
+    ///
+    /// The classes are not sharded, so with this enabled every allocation,
+    /// deallocation and resize updates counters shared by all threads.
+    pub const fn with_size_class_tracking(mut self) -> Self {
+        self.track_size_classes = true;
+        self
+    }
+
+    /// Sets the initial limit, see [`PeakAlloc::set_limit`].
+    pub const fn with_limit(mut self, bytes: usize) -> Self {
+        self.limit = AtomicUsize::new(bytes);
+        self
+    }
+
+    /// Sets the initial peak threshold, see [`PeakAlloc::set_peak_threshold`].
+    pub const fn with_peak_threshold(mut self, bytes: usize) -> Self {
+        self.peak_threshold = AtomicUsize::new(bytes);
+        self
+    }
+
+    /// Registers the initial peak callback, see
+    /// [`PeakAlloc::set_peak_callback`].
+    pub const fn with_peak_callback(mut self, cb: fn(usize)) -> Self {
+        self.peak_callback = AtomicPtr::new(cb as *mut ());
+        self
+    }
+
+    /// Names the allocator, to tell several instances apart in the `Debug`
+    /// output, the `metrics` export and `tracing` events.
+    pub const fn with_label(mut self, label: &'static str) -> Self {
+        self.label = Some(label);
+        self
+    }
+
+    /// Get the name given with [`PeakAlloc::with_label`].
+    pub const fn label(&self) -> Option<&'static str> {
+        self.label
+    }
+
+    /// Sets the initial budget, see [`PeakAlloc::set_budget`].
+    pub const fn with_budget(mut self, bytes: usize) -> Self {
+        self.budget = AtomicUsize::new(bytes);
+        self
+    }
+
+    /// Registers the initial budget callback, see
+    /// [`PeakAlloc::set_budget_callback`].
+    pub const fn with_budget_callback(mut self, cb: fn(usize) -> Admission) -> Self {
+        self.budget_callback = AtomicPtr::new(cb as *mut ());
+        self
+    }
+
+    /// Get the wrapped allocator, e.g. to read its own statistics.
+    ///
+    /// Allocating through it directly bypasses the instrumentation.
+    pub const fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    /// Runs `f` on the wrapped allocator and returns its result, e.g. to
+    /// refresh and read the statistics of an allocator which needs that, next
+    /// to the metrics of this one.
+    ///
+    /// `f` runs on the calling thread, outside of the allocator, so it may
+    /// allocate. Those allocations are tracked like any other.
+    pub fn with_inner_stats<R, F: FnOnce(&T) -> R>(&self, f: F) -> R {
+        f(&self.inner)
+    }
+
+    /// Resets the peak memory and the valley to the memory currently in use,
+    /// and the total allocated bytes, including the initial, growth and
+    /// zeroed bytes, the total deallocated bytes and the largest allocations
+    /// to 0.
+    ///
+    /// The memory in use, the counts and the histogram are kept, see
+    /// [`PeakAlloc::reset_all`] to reset everything.
+    ///
+    /// Memory which is still in use stays tracked, so the peak never
+    /// understates the memory actually in use. See
+    /// [`PeakAlloc::reset_current`] to exclude it from the measurement.
     pub fn reset_peak_memory(&self) {
-        self.peak_bytes_allocated.store(0, Ordering::SeqCst);
-        self.peak_bytes_allocated_tracker.store(0, Ordering::SeqCst);
+        let current = self.get_current_memory();
+        self.peak_bytes_allocated.store(current, Self::ORDERING);
+        self.min_bytes_allocated.store(current, Self::ORDERING);
+        self.total_bytes_allocated.store(0, Self::ORDERING);
+        self.total_bytes_deallocated.store(0, Self::ORDERING);
+        self.initial_alloc_bytes.store(0, Self::ORDERING);
+        self.realloc_growth_bytes.store(0, Self::ORDERING);
+        self.realloc_shrink_bytes.store(0, Self::ORDERING);
+        self.zeroed_bytes_allocated.store(0, Self::ORDERING);
+        self.largest_allocation.store(0, Self::ORDERING);
+        for top in &self.top_allocations {
+            top.store(0, Self::ORDERING);
+        }
+        #[cfg(feature = "backtrace")]
+        self.clear_largest_allocation_backtrace();
+        #[cfg(feature = "peak_timestamp")]
+        self.peak_timestamp.store(0, Self::ORDERING);
+        self.last_notified_peak.store(current, Self::ORDERING);
+        self.last_logged_peak.store(current, Self::ORDERING);
+        #[cfg(feature = "std")]
+        self.last_observed_peak.store(current, Self::ORDERING);
+        for (peak, current) in self.size_class_peak.iter().zip(&self.size_class_current) {
+            peak.store(current.load(Self::ORDERING), Self::ORDERING);
+        }
+    }
+
+    /// Resets the memory currently in use to 0, leaving the peak untouched.
+    ///
+    /// This excludes memory which is never going to be freed, e.g. caches
+    /// filled during setup, from subsequent measurements. Until enough new
+    /// memory has been allocated, the memory in use is no longer comparable
+    /// to the peak. Freeing memory allocated before the reset clamps the
+    /// memory in use at 0.
+    pub fn reset_current(&self) {
+        self.peak_bytes_allocated_tracker.store(0, Self::ORDERING);
+        self.min_bytes_allocated.store(0, Self::ORDERING);
+        self.scope_excluded.store(0, Self::ORDERING);
+        for shard in &self.shards {
+            shard.reset(Self::ORDERING);
+        }
+        for current in &self.size_class_current {
+            current.store(0, Self::ORDERING);
+        }
     }
 
-    /// Get the peak memory consumption
+    /// Returns the peak memory consumption and starts tracking a new peak.
+    ///
+    /// Like `reset_peak_memory`, the new peak starts at the memory currently
+    /// in use, but the other metrics are left untouched. The old peak is
+    /// swapped out atomically, so it can't miss an allocation. The current
+    /// memory is read before the swap, though, so the new peak doesn't include
+    /// an allocation racing with the call.
+    pub fn take_peak(&self) -> usize {
+        let current = self.get_current_memory();
+        self.last_notified_peak.store(current, Self::ORDERING);
+        self.last_logged_peak.store(current, Self::ORDERING);
+        #[cfg(feature = "std")]
+        self.last_observed_peak.store(current, Self::ORDERING);
+        #[cfg(feature = "peak_timestamp")]
+        self.peak_timestamp.store(0, Self::ORDERING);
+        self.peak_bytes_allocated.swap(current, Self::ORDERING)
+    }
+
+    /// Get the peak memory consumption. Never allocates, and is
+    /// async-signal-safe.
     pub fn get_peak_memory(&self) -> usize {
-        self.peak_bytes_allocated.load(Ordering::SeqCst)
+        self.peak_bytes_allocated.load(Self::ORDERING)
+    }
+
+    /// Whether the most recent allocation, or growing reallocation, raised the
+    /// peak memory consumption.
+    ///
+    /// A wrapping allocator can check this right after an allocation to only
+    /// capture expensive context, e.g. a backtrace, for the allocations which
+    /// set a new peak. The flag is shared by all threads, so under concurrent
+    /// allocations it may already belong to an allocation of another thread.
+    /// With sharding, only allocations which flush a shard can raise the peak.
+    pub fn last_alloc_was_peak(&self) -> bool {
+        self.last_alloc_was_peak.load(Self::ORDERING)
+    }
+
+    /// Get the minimum memory in use since the last reset, the valley. This is
+    /// the working set the program never dropped below.
+    ///
+    /// The valley is sampled after every deallocation. After resetting the
+    /// peak, it starts at the memory in use, a new allocator reports 0 until
+    /// its first deallocation.
+    pub fn get_min_memory(&self) -> usize {
+        match self.min_bytes_allocated.load(Self::ORDERING) {
+            // Nothing was freed yet, and the allocator started out empty.
+            NO_VALLEY => 0,
+            min => min,
+        }
+    }
+
+    /// Get the memory currently allocated through this allocator.
+    ///
+    /// Other threads may allocate or deallocate concurrently, so the returned
+    /// value can be momentarily stale. Never allocates, and is
+    /// async-signal-safe.
+    pub fn get_current_memory(&self) -> usize {
+        let buffered = self.shards.iter().fold(0isize, |sum, shard| {
+            sum.saturating_add(shard.load(Self::ORDERING))
+        });
+        self.peak_bytes_allocated_tracker
+            .load(Self::ORDERING)
+            .saturating_add(buffered)
+            .max(0) as usize
+    }
+
+    /// Get the peak memory consumption and the memory currently allocated.
+    ///
+    /// Unlike two separate reads, the returned peak is never below the
+    /// returned current memory, even while other threads allocate. Never
+    /// allocates.
+    pub fn peak_and_current(&self) -> (usize, usize) {
+        let current = self.get_current_memory();
+        // A concurrent allocation may have raised the tracker, but not yet the
+        // peak.
+        let peak = self.get_peak_memory().max(current);
+        (peak, current)
+    }
+
+    /// Get the memory in use at the first call of this method, e.g. the fixed
+    /// overhead of the runtime when called at the start of `main`.
+    ///
+    /// The baseline is captured only once and kept by all resets, later calls
+    /// return the same value. See [`PeakAlloc::peak_above_baseline`].
+    pub fn baseline(&self) -> usize {
+        let current = self.get_current_memory();
+        // The sentinel is never a real value, the memory in use is clamped to
+        // `isize::MAX`.
+        match self
+            .baseline
+            .compare_exchange(NO_BASELINE, current, Self::ORDERING, Self::ORDERING)
+        {
+            Ok(_) => current,
+            Err(baseline) => baseline,
+        }
+    }
+
+    /// Get the peak memory consumption on top of the [`PeakAlloc::baseline`],
+    /// i.e. the memory used by the program itself at its peak. Captures the
+    /// baseline if that didn't happen yet.
+    pub fn peak_above_baseline(&self) -> usize {
+        self.get_peak_memory().saturating_sub(self.baseline())
+    }
+
+    /// Whether the peak memory consumption is above `threshold` bytes.
+    pub fn peak_exceeded(&self, threshold: usize) -> bool {
+        self.get_peak_memory() > threshold
+    }
+
+    /// Whether the memory currently allocated is above `threshold` bytes,
+    /// e.g. to switch an algorithm to a more memory efficient strategy.
+    pub fn current_exceeded(&self, threshold: usize) -> bool {
+        self.get_current_memory() > threshold
+    }
+
+    /// Get the number of bytes which can still be allocated before the memory
+    /// in use reaches `limit`, or 0 if it already did.
+    pub fn remaining_until(&self, limit: usize) -> usize {
+        limit.saturating_sub(self.get_current_memory())
+    }
+
+    /// Get the peak memory consumption in the given unit.
+    pub fn get_peak_in(&self, unit: ByteUnit) -> f64 {
+        unit.convert(self.get_peak_memory())
+    }
+
+    /// Get the peak memory consumption in KiB.
+    pub fn get_peak_memory_kib(&self) -> f64 {
+        self.get_peak_in(ByteUnit::KiB)
+    }
+
+    /// Get the peak memory consumption in MiB.
+    pub fn get_peak_memory_mib(&self) -> f64 {
+        self.get_peak_in(ByteUnit::MiB)
+    }
+
+    /// Get the memory currently allocated in the given unit.
+    pub fn get_current_in(&self, unit: ByteUnit) -> f64 {
+        unit.convert(self.get_current_memory())
+    }
+
+    /// Get the memory currently allocated in KiB.
+    pub fn get_current_memory_kib(&self) -> f64 {
+        self.get_current_in(ByteUnit::KiB)
+    }
+
+    /// Get the memory currently allocated in MiB.
+    pub fn get_current_memory_mib(&self) -> f64 {
+        self.get_current_in(ByteUnit::MiB)
+    }
+
+    /// Get the total number of bytes allocated, ignoring deallocations.
+    ///
+    /// Unlike the peak, this keeps growing for code that allocates and frees
+    /// in quick succession. Growing a block with `realloc` counts the
+    /// additional bytes, shrinking it does not count at all.
+    ///
+    /// Saturates at `usize::MAX`, see [`PeakAlloc::get_total_allocated_u64`]
+    /// for long running processes on 32 bit targets.
+    pub fn get_total_allocated(&self) -> usize {
+        self.get_total_allocated_u64().min(usize::MAX as u64) as usize
+    }
+
+    /// Get the total number of bytes allocated, ignoring deallocations, in a
+    /// 64 bit counter.
+    ///
+    /// Unlike [`PeakAlloc::get_total_allocated`], this doesn't saturate on 32
+    /// bit targets, unless they lack 64 bit atomics.
+    pub fn get_total_allocated_u64(&self) -> u64 {
+        let total = self.total_bytes_allocated.load(Self::ORDERING);
+        #[cfg(not(target_has_atomic = "64"))]
+        let total = total as u64;
+        total
+    }
+
+    /// Get the total number of bytes deallocated, including the bytes freed by
+    /// shrinking a block with `realloc`.
+    ///
+    /// The difference to [`PeakAlloc::get_total_allocated`] is the net growth
+    /// since the last reset, and the deallocated bytes over a window of time
+    /// are the churn. Saturates at `usize::MAX`, see
+    /// [`PeakAlloc::get_total_deallocated_u64`].
+    pub fn get_total_deallocated(&self) -> usize {
+        self.get_total_deallocated_u64().min(usize::MAX as u64) as usize
+    }
+
+    /// Get the total number of bytes deallocated in a 64 bit counter, like
+    /// [`PeakAlloc::get_total_allocated_u64`].
+    pub fn get_total_deallocated_u64(&self) -> u64 {
+        let total = self.total_bytes_deallocated.load(Self::ORDERING);
+        #[cfg(not(target_has_atomic = "64"))]
+        let total = total as u64;
+        total
+    }
+
+    /// Get the number of bytes requested by new allocations, e.g. the
+    /// capacity passed to `Vec::with_capacity`.
+    ///
+    /// Together with [`PeakAlloc::get_realloc_growth_bytes`] this adds up to
+    /// [`PeakAlloc::get_total_allocated`].
+    pub fn get_initial_alloc_bytes(&self) -> usize {
+        self.initial_alloc_bytes.load(Self::ORDERING)
+    }
+
+    /// Get the number of bytes added by growing existing allocations with
+    /// `realloc`, e.g. when a `Vec` outgrows its capacity.
+    pub fn get_realloc_growth_bytes(&self) -> usize {
+        self.realloc_growth_bytes.load(Self::ORDERING)
+    }
+
+    /// Get the number of bytes removed by shrinking existing allocations with
+    /// `realloc`, e.g. by `Vec::shrink_to_fit`. They are included in
+    /// [`PeakAlloc::get_total_deallocated`].
+    pub fn get_realloc_shrink_bytes(&self) -> usize {
+        self.realloc_shrink_bytes.load(Self::ORDERING)
+    }
+
+    /// Get the number of bytes allocated with `alloc_zeroed`, e.g. by
+    /// `vec![0; n]`.
+    ///
+    /// Zeroing is more expensive than a plain allocation, unless the memory
+    /// comes fresh from the operating system. Its share of the total allocated
+    /// bytes points at buffers which may not need to be initialized.
+    pub fn get_zeroed_bytes(&self) -> usize {
+        self.zeroed_bytes_allocated.load(Self::ORDERING)
+    }
+
+    /// Get the number of allocations made.
+    ///
+    /// Resizing a block with `realloc` is neither counted as an allocation
+    /// nor as a deallocation.
+    pub fn get_alloc_count(&self) -> usize {
+        self.alloc_count.load(Self::ORDERING)
+    }
+
+    /// Get the number of deallocations made.
+    ///
+    /// A large gap to [`get_alloc_count`](Self::get_alloc_count) at the end
+    /// of a program hints at a leak.
+    pub fn get_dealloc_count(&self) -> usize {
+        self.dealloc_count.load(Self::ORDERING)
+    }
+
+    /// Get the number of reallocations which moved the block to a new
+    /// address, which requires copying its contents.
+    pub fn get_realloc_moved_count(&self) -> usize {
+        self.realloc_moved_count.load(Self::ORDERING)
+    }
+
+    /// Get the number of reallocations which resized the block in place.
+    pub fn get_realloc_inplace_count(&self) -> usize {
+        self.realloc_inplace_count.load(Self::ORDERING)
+    }
+
+    /// Get the number of bytes copied by reallocations which moved the block,
+    /// i.e. the smaller of the old and the new size of each of them.
+    ///
+    /// This is the hidden cost of growing collections without a capacity
+    /// hint. The inner allocator may copy less, e.g. by remapping pages.
+    pub fn get_realloc_copied_bytes(&self) -> usize {
+        self.realloc_copied_bytes.load(Self::ORDERING)
+    }
+
+    /// Get the size of the largest single allocation.
+    ///
+    /// A block grown with `realloc` counts with its new total size, as that is
+    /// what has to fit in memory at once.
+    pub fn get_largest_allocation(&self) -> usize {
+        self.largest_allocation.load(Self::ORDERING)
+    }
+
+    /// Get the sizes of the largest allocations, in descending order and
+    /// padded with 0.
+    ///
+    /// Growing a block with `realloc` records its new size like an
+    /// allocation, so a growing buffer may show up more than once. The list is
+    /// approximate: sizes allocated concurrently with an update of the list
+    /// are skipped. Reset by [`PeakAlloc::reset_peak_memory`].
+    pub fn top_allocations(&self) -> [usize; TOP_ALLOCATIONS] {
+        let mut sizes = [0; TOP_ALLOCATIONS];
+        for (size, top) in sizes.iter_mut().zip(&self.top_allocations) {
+            *size = top.load(Self::ORDERING);
+        }
+        sizes
+    }
+
+    /// Get the estimated padding of the allocations in use, see
+    /// [`with_alignment_tracking`](Self::with_alignment_tracking). This is
+    /// always 0 if alignment tracking is not enabled.
+    pub fn get_alignment_overhead(&self) -> usize {
+        self.alignment_overhead.load(Self::ORDERING)
+    }
+
+    /// Get the number of allocations by size, in buckets of powers of two.
+    ///
+    /// Sizes are rounded up to the next power of two, bucket `i` counts the
+    /// allocations of more than `2^(i-1)` and at most `2^i` bytes. Bucket 0
+    /// counts allocations of 0 and 1 bytes, the last bucket also counts all
+    /// larger allocations. Resizing a block with `realloc` is not counted.
+    pub fn size_histogram(&self) -> [usize; HISTOGRAM_BUCKETS] {
+        let mut histogram = [0; HISTOGRAM_BUCKETS];
+        for (count, bucket) in histogram.iter_mut().zip(&self.size_histogram) {
+            *count = bucket.load(Self::ORDERING);
+        }
+        histogram
+    }
+
+    /// Get the peak memory consumption of each size class, i.e. the most bytes
+    /// of allocations in a bucket of the [`PeakAlloc::size_histogram`] which
+    /// were in use at the same time.
+    ///
+    /// This shows whether the peak is driven by many small allocations or by
+    /// a few large ones. Each class has its own peak, which it may have
+    /// reached at another time than the overall peak, so the peaks can add up
+    /// to more than [`PeakAlloc::get_peak_memory`]. A reallocation moves the
+    /// bytes of a block to the class of its new size. Only tracked with
+    /// [`PeakAlloc::with_size_class_tracking`], otherwise all peaks are 0.
+    pub fn peak_by_size_class(&self) -> [usize; HISTOGRAM_BUCKETS] {
+        let mut peaks = [0; HISTOGRAM_BUCKETS];
+        for (peak, class) in peaks.iter_mut().zip(&self.size_class_peak) {
+            *peak = class.load(Self::ORDERING);
+        }
+        peaks
+    }
+
+    /// Iterates over the non-empty buckets of the [`PeakAlloc::size_histogram`],
+    /// yielding the allocation sizes a bucket holds and its count.
+    ///
+    /// The range of the last bucket ends at `usize::MAX`, which it also holds.
+    pub fn histogram_iter(&self) -> impl Iterator<Item = (Range<usize>, usize)> {
+        let histogram = self.size_histogram();
+        (0..HISTOGRAM_BUCKETS)
+            .map(move |index| (histogram::bucket_range(index), histogram[index]))
+            .filter(|&(_, count)| count > 0)
+    }
+
+    /// Computes approximate statistics of the allocation sizes from the
+    /// [`PeakAlloc::size_histogram`].
+    pub fn histogram_summary(&self) -> HistogramSummary {
+        HistogramSummary::new(&self.size_histogram())
+    }
+
+    /// Get the number of allocations by alignment, bucket `i` counts the
+    /// allocations aligned to `2^i` bytes.
+    ///
+    /// Large alignments, e.g. for SIMD or pages, may waste memory, see
+    /// [`PeakAlloc::with_alignment_tracking`], which also needs to be enabled
+    /// for this to be tracked. Resizing a block with `realloc` is not counted.
+    pub fn alignment_histogram(&self) -> [usize; ALIGNMENT_BUCKETS] {
+        let mut histogram = [0; ALIGNMENT_BUCKETS];
+        for (count, bucket) in histogram.iter_mut().zip(&self.alignment_histogram) {
+            *count = bucket.load(Self::ORDERING);
+        }
+        histogram
+    }
+
+    /// Get the alignment most allocations requested, or `None` if nothing was
+    /// allocated. Ties are resolved in favor of the larger alignment.
+    pub fn most_common_alignment(&self) -> Option<usize> {
+        let histogram = self.alignment_histogram();
+        let (index, &count) = histogram
+            .iter()
+            .enumerate()
+            .max_by_key(|&(_, &count)| count)?;
+        (count > 0).then_some(1 << index)
+    }
+
+    /// Reads all metrics at once.
+    ///
+    /// Each counter is read exactly once, but not all at the same instant, so
+    /// concurrent allocations may still make the values slightly inconsistent.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            peak: self.get_peak_memory(),
+            current: self.get_current_memory(),
+            total_allocated: self.get_total_allocated(),
+            alloc_count: self.get_alloc_count(),
+            dealloc_count: self.get_dealloc_count(),
+            largest_allocation: self.get_largest_allocation(),
+            zeroed_bytes: self.get_zeroed_bytes(),
+        }
+    }
+
+    /// Reads all metrics like [`PeakAlloc::snapshot`] and starts a new
+    /// interval, e.g. for a reporting loop which logs the metrics of every
+    /// interval.
+    ///
+    /// The peak is reset to the memory in use, like [`PeakAlloc::take_peak`],
+    /// and the total allocated bytes, the allocation and deallocation counts,
+    /// the zeroed bytes and the largest allocation are reset to 0. The memory
+    /// in use is kept. Of the metrics outside of the [`Snapshot`], the initial
+    /// and the realloc growth bytes, which add up to the total, and the top
+    /// allocations are reset to 0 as well. So are the total deallocated and
+    /// the realloc shrink bytes, so the difference of the totals stays the net
+    /// growth of the interval. Everything else is kept. Each counter is read
+    /// and reset in a single atomic operation, so no allocation is lost
+    /// between two intervals, but the counters are not all reset at the same
+    /// instant.
+    pub fn take_snapshot(&self) -> Snapshot {
+        let current = self.get_current_memory();
+        let peak = self.take_peak().max(current);
+        let total = self.total_bytes_allocated.swap(0, Self::ORDERING);
+        #[cfg(not(target_has_atomic = "64"))]
+        let total = total as u64;
+        self.total_bytes_deallocated.store(0, Self::ORDERING);
+        self.initial_alloc_bytes.store(0, Self::ORDERING);
+        self.realloc_growth_bytes.store(0, Self::ORDERING);
+        self.realloc_shrink_bytes.store(0, Self::ORDERING);
+        for top in &self.top_allocations {
+            top.store(0, Self::ORDERING);
+        }
+        Snapshot {
+            peak,
+            current,
+            total_allocated: total.min(usize::MAX as u64) as usize,
+            alloc_count: self.alloc_count.swap(0, Self::ORDERING),
+            dealloc_count: self.dealloc_count.swap(0, Self::ORDERING),
+            largest_allocation: self.largest_allocation.swap(0, Self::ORDERING),
+            zeroed_bytes: self.zeroed_bytes_allocated.swap(0, Self::ORDERING),
+        }
+    }
+
+    /// Starts measuring the peak memory consumption of a region, which ends
+    /// when the returned guard is dropped.
+    ///
+    /// Unlike `reset_peak_memory`, this leaves the global peak untouched and
+    /// can be nested.
+    pub fn scope(&self) -> PeakGuard<'_, T, RELAXED> {
+        PeakGuard::new(self)
+    }
+
+    /// Saves the memory in use and the peaks, including the peaks of
+    /// enclosing scopes, to restore them with [`SavedState::restore`].
+    ///
+    /// Restoring makes the enclosing measurements forget everything in
+    /// between, e.g. to exclude a noisy sub-measurement. Unlike a
+    /// [`Checkpoint`], this writes the state back, so everything other threads
+    /// allocated or freed in between is forgotten as well, and memory which
+    /// was allocated in between and is freed later is subtracted from the
+    /// restored memory in use. The counters and totals are not restored. With
+    /// [`PeakAlloc::with_sharding`], changes buffered in the shards are not
+    /// saved either.
+    pub fn save(&self) -> SavedState<'_, T, RELAXED> {
+        SavedState::new(self)
+    }
+
+    /// Runs `f` and returns its result together with the peak memory
+    /// consumption during its execution.
+    ///
+    /// Allocations made concurrently by other threads are included in the
+    /// measurement.
+    pub fn measure<R, F: FnOnce() -> R>(&self, f: F) -> (R, usize) {
+        let guard = self.scope();
+        let result = f();
+        (result, guard.finish())
+    }
+
+    /// Builds a value and returns it together with the peak memory consumption
+    /// during its construction.
+    ///
+    /// This is [`PeakAlloc::measure`] for the question of how much building
+    /// a data structure took at its peak, including intermediate buffers which
+    /// were freed again. The value is kept alive, so the memory it holds is
+    /// still in use afterwards, and the peak is at least as large as that.
+    pub fn measure_value<V, F: FnOnce() -> V>(&self, make: F) -> (V, usize) {
+        self.measure(make)
+    }
+
+    /// Runs `f` on every item and returns each result together with the peak
+    /// memory consumption while processing that item, e.g. to find the input
+    /// of a benchmark which peaks highest.
+    ///
+    /// Each item is measured with [`PeakAlloc::measure`], so its peak is
+    /// relative to the memory in use before it, which includes the results of
+    /// the previous items. Allocations of other threads are included as well,
+    /// so this is meant for single threaded benchmarks.
+    #[cfg(feature = "std")]
+    #[cfg_attr(doc_cfg, doc(cfg(feature = "std")))]
+    pub fn measure_each<I, R, F>(&self, items: I, mut f: F) -> Vec<(R, usize)>
+    where
+        I: IntoIterator,
+        F: FnMut(I::Item) -> R,
+    {
+        items
+            .into_iter()
+            .map(|item| self.measure(|| f(item)))
+            .collect()
+    }
+
+    /// Registers a callback, which is invoked with the new peak whenever the
+    /// peak memory consumption increases.
+    ///
+    /// The callback runs inside the allocator, on the thread which allocated.
+    /// It must not allocate, which rules out most logging and formatting, and
+    /// should return quickly.
+    pub fn set_peak_callback(&self, cb: fn(usize)) {
+        self.peak_callback.store(cb as *mut (), Self::ORDERING);
+    }
+
+    /// Only notifies about a new peak, via the peak callback or a `tracing`
+    /// event, once it exceeds the previously notified peak by at least
+    /// `bytes`. The default of 0 notifies about every new peak.
+    pub fn set_peak_threshold(&self, bytes: usize) {
+        self.peak_threshold.store(bytes, Self::ORDERING);
+    }
+
+    /// Removes the callback registered with `set_peak_callback`.
+    pub fn clear_peak_callback(&self) {
+        self.peak_callback.store(ptr::null_mut(), Self::ORDERING);
+    }
+
+    /// Checks every `n` allocations whether the peak grew since the last
+    /// check, and if so flags it to be picked up by
+    /// [`PeakAlloc::take_logged_peak`]. 0 turns the check off, which is the
+    /// default.
+    ///
+    /// Logging from inside the allocator would allocate and recurse into it,
+    /// so the allocator only sets an atomic flag, and the logging is left to
+    /// regular code polling it, e.g. once per iteration of a main loop. Unlike
+    /// the sampler, this doesn't need a thread.
+    pub fn log_every(&self, n: usize) {
+        self.log_interval.store(n, Self::ORDERING);
+    }
+
+    /// Returns the peak memory consumption if [`PeakAlloc::log_every`] flagged
+    /// a new peak since the last call, and clears the flag.
+    pub fn take_logged_peak(&self) -> Option<usize> {
+        self.peak_log_pending
+            .swap(false, Self::ORDERING)
+            .then(|| self.get_peak_memory())
+    }
+
+    /// Prints the peak memory consumption to stderr if
+    /// [`PeakAlloc::log_every`] flagged a new peak since the last call.
+    ///
+    /// This allocates, so it must not be called from inside the allocator.
+    #[cfg(feature = "std")]
+    #[cfg_attr(doc_cfg, doc(cfg(feature = "std")))]
+    pub fn print_logged_peak(&self) {
+        if let Some(peak) = self.take_logged_peak() {
+            std::eprintln!("new peak memory consumption: {}", HumanBytes(peak));
+        }
+    }
+
+    /// Limits the memory in use to `bytes`, allocations which would exceed it
+    /// fail by returning a null pointer. A limit of 0 means unlimited.
+    ///
+    /// The check is not atomic with the allocation, so concurrent allocations
+    /// may overshoot the limit slightly.
+    pub fn set_limit(&self, bytes: usize) {
+        self.limit.store(bytes, Self::ORDERING);
+    }
+
+    /// Whether an allocation failed because of the limit, or because the
+    /// budget callback failed it, since the last call to
+    /// [`PeakAlloc::clear_memory_limit_reached`].
+    ///
+    /// Allocations which the wrapped allocator fails on its own, e.g. when it
+    /// is out of memory, don't set this, so the two causes of a null pointer
+    /// can be told apart.
+    pub fn memory_limit_reached(&self) -> bool {
+        self.limit_hit.load(Self::ORDERING)
+    }
+
+    /// Forgets that an allocation failed because of the limit, see
+    /// [`PeakAlloc::memory_limit_reached`].
+    pub fn clear_memory_limit_reached(&self) {
+        self.limit_hit.store(false, Self::ORDERING);
+    }
+
+    /// Sets a soft limit on the memory in use. Before an allocation would
+    /// exceed it, the budget callback decides between allocating anyway,
+    /// failing and waiting for memory to be freed. A budget of 0, the default,
+    /// disables the callback.
+    pub fn set_budget(&self, bytes: usize) {
+        self.budget.store(bytes, Self::ORDERING);
+    }
+
+    /// Registers a callback, which is invoked with the memory which would be in
+    /// use after an allocation exceeding the budget, and decides whether the
+    /// allocation goes ahead, see [`Admission`].
+    ///
+    /// Like the peak callback, it runs inside the allocator and must not
+    /// allocate. With [`Admission::Wait`], the allocating thread spins until
+    /// other threads free enough memory, and the callback is invoked again
+    /// on every iteration. This deadlocks if the memory is never freed, e.g.
+    /// because the waiting thread holds a lock the others need to make
+    /// progress, or because it is the only thread.
+    pub fn set_budget_callback(&self, cb: fn(usize) -> Admission) {
+        self.budget_callback.store(cb as *mut (), Self::ORDERING);
+    }
+
+    /// Removes the callback registered with `set_budget_callback`.
+    pub fn clear_budget_callback(&self) {
+        self.budget_callback.store(ptr::null_mut(), Self::ORDERING);
+    }
+
+    /// Ignores allocations smaller than `bytes`, they are neither counted as
+    /// allocations and deallocations nor included in the memory in use. A
+    /// floor of 0, the default, tracks all allocations.
+    ///
+    /// Deallocations are only given the size of their layout, so the floor
+    /// applies by size at both ends. It should be set before allocating, as
+    /// memory allocated below a different floor is freed asymmetrically.
+    pub fn set_min_tracked_size(&self, bytes: usize) {
+        self.min_tracked_size.store(bytes, Self::ORDERING);
+    }
+
+    /// Marks the current point to measure the growth of the peak from.
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            peak: self.get_peak_memory(),
+            current: self.get_current_memory(),
+        }
+    }
+
+    /// Get how much the peak memory consumption grew since the checkpoint.
+    ///
+    /// This is 0 as long as the peak recorded before the checkpoint was not
+    /// exceeded, resetting the peak in the meantime doesn't make it negative.
+    pub fn peak_since(&self, checkpoint: &Checkpoint) -> usize {
+        self.get_peak_memory().saturating_sub(checkpoint.peak)
+    }
+
+    /// Resets the allocation, deallocation and reallocation counts, the bytes
+    /// copied by reallocations and the size and alignment histograms to 0
+    ///
+    /// The counts are not affected by `reset_peak_memory`.
+    pub fn reset_counts(&self) {
+        self.alloc_count.store(0, Self::ORDERING);
+        self.dealloc_count.store(0, Self::ORDERING);
+        self.realloc_moved_count.store(0, Self::ORDERING);
+        self.realloc_inplace_count.store(0, Self::ORDERING);
+        self.realloc_copied_bytes.store(0, Self::ORDERING);
+        for bucket in self.size_histogram.iter().chain(&self.alignment_histogram) {
+            bucket.store(0, Self::ORDERING);
+        }
+    }
+
+    /// Resets all metrics to 0, as if no memory had ever been allocated.
+    ///
+    /// This combines [`PeakAlloc::reset_current`],
+    /// [`PeakAlloc::reset_peak_memory`] and [`PeakAlloc::reset_counts`], and
+    /// also resets the alignment overhead, whether the limit was reached,
+    /// whether the last allocation raised the peak, a pending peak of
+    /// [`PeakAlloc::log_every`], the report of the named regions and the
+    /// outstanding allocations of the `strict` feature.
+    /// The configuration, e.g. the limit and the peak callback, is kept.
+    /// Memory which is still in use is no longer tracked, freeing it clamps
+    /// the memory in use at 0.
+    pub fn reset_all(&self) {
+        self.reset_current();
+        self.alignment_overhead.store(0, Self::ORDERING);
+        self.clear_memory_limit_reached();
+        self.reset_peak_memory();
+        self.reset_counts();
+        self.last_alloc_was_peak.store(false, Self::ORDERING);
+        self.peak_log_pending.store(false, Self::ORDERING);
+        #[cfg(feature = "std")]
+        self.region_peaks
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .clear();
+        #[cfg(feature = "strict")]
+        self.clear_outstanding();
+    }
+
+    /// Whether an allocation of `bytes` must fail, because of the limit or
+    /// the budget callback.
+    #[inline]
+    fn refuses(&self, bytes: usize) -> bool {
+        if cfg!(feature = "disabled") {
+            return false;
+        }
+        #[cfg(feature = "std")]
+        forbid::check(bytes);
+        let limit = self.limit.load(Self::ORDERING);
+        let refused = (limit != 0 && self.get_current_memory().saturating_add(bytes) > limit)
+            || !self.admits(bytes);
+        if refused {
+            self.limit_hit.store(true, Self::ORDERING);
+        }
+        refused
+    }
+
+    #[inline]
+    fn admits(&self, bytes: usize) -> bool {
+        let budget = self.budget.load(Self::ORDERING);
+        if budget == 0 {
+            return true;
+        }
+        loop {
+            let requested = self.get_current_memory().saturating_add(bytes);
+            let cb = self.budget_callback.load(Self::ORDERING);
+            if requested <= budget || cb.is_null() {
+                return true;
+            }
+            // Only ever set from a `fn(usize) -> Admission` in
+            // `set_budget_callback`.
+            let cb = unsafe { mem::transmute::<*mut (), fn(usize) -> Admission>(cb) };
+            match cb(requested) {
+                Admission::Continue => return true,
+                Admission::Fail => return false,
+                Admission::Wait => {
+                    #[cfg(feature = "std")]
+                    std::thread::yield_now();
+                    #[cfg(not(feature = "std"))]
+                    core::hint::spin_loop();
+                }
+            }
+        }
+    }
+
+    #[inline]
+    fn record_alloc(&self, layout: Layout) {
+        if Self::is_paused() {
+            return;
+        }
+        let bytes = layout.size();
+        if self.is_below_floor(bytes) {
+            return;
+        }
+        let count = self
+            .alloc_count
+            .fetch_add(1, Self::ORDERING)
+            .wrapping_add(1);
+        self.size_histogram[histogram::bucket(bytes)].fetch_add(1, Self::ORDERING);
+        self.grow_size_class(bytes);
+        self.track_largest(bytes);
+        if self.track_alignment {
+            self.alignment_histogram[layout.align().trailing_zeros() as usize]
+                .fetch_add(1, Self::ORDERING);
+            self.add_alignment_overhead(alignment_padding(bytes, layout.align()));
+        }
+        self.initial_alloc_bytes.fetch_add(bytes, Self::ORDERING);
+        self.track_alloc(bytes);
+        let interval = self.log_interval.load(Self::ORDERING);
+        if interval != 0 && count.is_multiple_of(interval) {
+            self.flag_logged_peak();
+        }
+    }
+
+    #[cold]
+    fn flag_logged_peak(&self) {
+        let peak = self.get_peak_memory();
+        if self.last_logged_peak.fetch_max(peak, Self::ORDERING) < peak {
+            self.peak_log_pending.store(true, Self::ORDERING);
+        }
+    }
+
+    #[inline]
+    fn grow_size_class(&self, bytes: usize) {
+        if !self.track_size_classes {
+            return;
+        }
+        let class = histogram::bucket(bytes);
+        let prev = self.size_class_current[class].fetch_add(bytes, Self::ORDERING);
+        self.size_class_peak[class].fetch_max(prev.saturating_add(bytes), Self::ORDERING);
+    }
+
+    /// Like the memory in use, each class is clamped at 0 when memory which
+    /// was never tracked is freed.
+    #[inline]
+    fn shrink_size_class(&self, bytes: usize) {
+        if !self.track_size_classes {
+            return;
+        }
+        let _ = self.size_class_current[histogram::bucket(bytes)].fetch_update(
+            Self::ORDERING,
+            Self::ORDERING,
+            |current| Some(current.saturating_sub(bytes)),
+        );
+    }
+
+    #[inline]
+    fn track_largest(&self, bytes: usize) {
+        #[cfg(not(feature = "backtrace"))]
+        self.largest_allocation.fetch_max(bytes, Self::ORDERING);
+        #[cfg(feature = "backtrace")]
+        if self.largest_allocation.fetch_max(bytes, Self::ORDERING) < bytes {
+            self.capture_largest_backtrace();
+        }
+        if bytes > self.top_allocations[TOP_ALLOCATIONS - 1].load(Self::ORDERING) {
+            self.insert_top_allocation(bytes);
+        }
+    }
+
+    /// Keeping the sizes sorted takes several operations, so they are updated
+    /// under a try-lock. Sizes which arrive while another thread holds the
+    /// lock are dropped rather than waited for.
+    #[cold]
+    fn insert_top_allocation(&self, bytes: usize) {
+        if self
+            .top_allocations_lock
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            return;
+        }
+        let mut carry = bytes;
+        for top in &self.top_allocations {
+            let size = top.load(Self::ORDERING);
+            if carry > size {
+                top.store(carry, Self::ORDERING);
+                carry = size;
+            }
+        }
+        self.top_allocations_lock.store(false, Ordering::Release);
+    }
+
+    #[inline]
+    fn record_zeroed(&self, bytes: usize) {
+        if Self::is_paused() || self.is_below_floor(bytes) {
+            return;
+        }
+        self.zeroed_bytes_allocated.fetch_add(bytes, Self::ORDERING);
+    }
+
+    /// Whether nothing is tracked, because of the `disabled` feature or a
+    /// [`PeakAlloc::suspend`] on the current thread.
+    #[inline]
+    fn is_paused() -> bool {
+        if cfg!(feature = "disabled") {
+            return true;
+        }
+        #[cfg(feature = "std")]
+        return suspend::is_suspended();
+        #[cfg(not(feature = "std"))]
+        return false;
+    }
+
+    #[inline]
+    fn is_below_floor(&self, bytes: usize) -> bool {
+        bytes < self.min_tracked_size.load(Self::ORDERING)
+    }
+
+    #[inline]
+    fn record_dealloc(&self, layout: Layout) {
+        if Self::is_paused() {
+            return;
+        }
+        if self.is_below_floor(layout.size()) {
+            return;
+        }
+        self.dealloc_count.fetch_add(1, Self::ORDERING);
+        self.shrink_size_class(layout.size());
+        if self.track_alignment {
+            self.sub_alignment_overhead(alignment_padding(layout.size(), layout.align()));
+        }
+        self.track_dealloc(layout.size());
+    }
+
+    #[inline]
+    fn record_realloc(&self, old_ptr: *mut u8, new_ptr: *mut u8, copied: usize) {
+        if Self::is_paused() {
+            return;
+        }
+        if old_ptr == new_ptr {
+            self.realloc_inplace_count.fetch_add(1, Self::ORDERING);
+        } else {
+            self.realloc_moved_count.fetch_add(1, Self::ORDERING);
+            self.realloc_copied_bytes.fetch_add(copied, Self::ORDERING);
+        }
+    }
+
+    #[inline]
+    fn add_alignment_overhead(&self, bytes: usize) {
+        self.alignment_overhead.fetch_add(bytes, Self::ORDERING);
+    }
+
+    #[inline]
+    fn sub_alignment_overhead(&self, bytes: usize) {
+        let _ = self
+            .alignment_overhead
+            .fetch_update(Self::ORDERING, Self::ORDERING, |overhead| {
+                Some(overhead.saturating_sub(bytes))
+            });
+    }
+
+    #[inline]
+    fn add_to_total(total: &TotalCounter, bytes: usize) {
+        #[cfg(target_has_atomic = "64")]
+        total.fetch_add(bytes as u64, Self::ORDERING);
+        #[cfg(not(target_has_atomic = "64"))]
+        let _ = total.fetch_update(Self::ORDERING, Self::ORDERING, |total| {
+            Some(total.saturating_add(bytes))
+        });
     }
 
     #[inline]
     fn track_alloc(&self, bytes: usize) {
+        Self::add_to_total(&self.total_bytes_allocated, bytes);
+        let new_peak = if self.sharded {
+            self.buffer_in_shard(signed(bytes))
+        } else {
+            self.increase_tracker(bytes)
+        };
+        // Only written when it changes, to keep the cache line shared while
+        // the peak stays put.
+        if self.last_alloc_was_peak.load(Self::ORDERING) != new_peak {
+            self.last_alloc_was_peak.store(new_peak, Self::ORDERING);
+        }
+    }
+
+    /// Returns whether the peak was raised.
+    #[inline]
+    fn increase_tracker(&self, bytes: usize) -> bool {
         let prev = self
             .peak_bytes_allocated_tracker
-            .fetch_add(bytes as isize, Ordering::SeqCst);
-        let current_peak = (prev + bytes as isize).max(0) as usize;
-        self.peak_bytes_allocated
-            .fetch_max(current_peak, Ordering::SeqCst);
+            .fetch_update(Self::ORDERING, Self::ORDERING, |current| {
+                Some(current.saturating_add(signed(bytes)))
+            })
+            .unwrap_or_else(|current| current);
+        // The tracker and the peak can't be updated in one atomic operation,
+        // so the peak may briefly lag behind the tracker. It is only ever
+        // raised to a value the tracker actually held though, and catches up
+        // as soon as the allocating thread gets here.
+        let current_peak = prev.saturating_add(signed(bytes)).max(0) as usize;
+        let prev_peak = self
+            .peak_bytes_allocated
+            .fetch_max(current_peak, Self::ORDERING);
+        if current_peak > prev_peak {
+            #[cfg(feature = "peak_timestamp")]
+            self.stamp_peak();
+            self.notify_peak(current_peak);
+        }
+        let excluded = self.scope_excluded.load(Self::ORDERING);
+        self.scope_peak
+            .fetch_max(current_peak.saturating_sub(excluded), Self::ORDERING);
+        current_peak > prev_peak
+    }
+
+    #[inline]
+    fn notify_peak(&self, peak: usize) {
+        let last = self.last_notified_peak.load(Self::ORDERING);
+        if peak <= last || peak - last < self.peak_threshold.load(Self::ORDERING) {
+            return;
+        }
+        // Concurrent new peaks are only notified once.
+        if self
+            .last_notified_peak
+            .compare_exchange(last, peak, Self::ORDERING, Self::ORDERING)
+            .is_err()
+        {
+            return;
+        }
+        let cb = self.peak_callback.load(Self::ORDERING);
+        if !cb.is_null() {
+            // Only ever set from a `fn(usize)` in `set_peak_callback`.
+            let cb = unsafe { mem::transmute::<*mut (), fn(usize)>(cb) };
+            cb(peak);
+        }
+        #[cfg(feature = "tracing")]
+        reentrancy::guarded(|| {
+            tracing::debug!(
+                target: "peakmem_alloc",
+                label = self.label,
+                peak,
+                delta = peak - last,
+                "new peak memory consumption"
+            )
+        });
     }
 
     #[inline]
     fn track_dealloc(&self, bytes: usize) {
-        self.peak_bytes_allocated_tracker
-            .fetch_sub(bytes as isize, Ordering::SeqCst);
+        Self::add_to_total(&self.total_bytes_deallocated, bytes);
+        if self.sharded {
+            self.buffer_in_shard(-signed(bytes));
+        } else {
+            self.decrease_tracker(bytes);
+        }
+    }
+
+    /// Memory which was allocated before this allocator was installed, or by
+    /// another allocator, may be freed through it. The tracker is clamped at
+    /// 0 in that case, as a negative baseline would hide subsequent peaks.
+    #[inline]
+    fn decrease_tracker(&self, bytes: usize) {
+        let prev = self
+            .peak_bytes_allocated_tracker
+            .fetch_update(Self::ORDERING, Self::ORDERING, |current| {
+                Some(current.saturating_sub(signed(bytes)).max(0))
+            })
+            .unwrap_or_else(|current| current);
+        let current = prev.saturating_sub(signed(bytes)).max(0) as usize;
+        self.min_bytes_allocated.fetch_min(current, Self::ORDERING);
+        if self.scope_excluded.load(Self::ORDERING) != 0 {
+            self.release_excluded(current);
+        }
+    }
+
+    /// Stops hiding the memory of excluded scopes once the memory in use drops
+    /// below the level it had when they were excluded, assuming the freed
+    /// memory was the excluded one. The memory in use seen by the scopes never
+    /// drops below that level while something is excluded.
+    #[cold]
+    fn release_excluded(&self, current: usize) {
+        let level = self.scope_excluded_level.load(Self::ORDERING);
+        let live = current.saturating_sub(level);
+        self.scope_excluded.fetch_min(live, Self::ORDERING);
+    }
+
+    #[inline]
+    fn buffer_in_shard(&self, delta: isize) -> bool {
+        let shard = &self.shards[shard::current_index()];
+        match shard.buffer(delta, Self::ORDERING) {
+            Some(delta) if delta >= 0 => self.increase_tracker(delta as usize),
+            Some(delta) => self.flush_all_shards(delta),
+            None => false,
+        }
+    }
+
+    /// Applies a negative flush together with everything buffered in the
+    /// other shards. Blocks may be allocated on one shard and freed on
+    /// another, so clamping the tracker at 0 with the allocations still
+    /// buffered elsewhere would lose part of the deallocations. Returns
+    /// whether the peak was raised.
+    #[cold]
+    fn flush_all_shards(&self, delta: isize) -> bool {
+        let delta = self.shards.iter().fold(delta, |sum, shard| {
+            sum.saturating_add(shard.take(Self::ORDERING))
+        });
+        if delta >= 0 {
+            self.increase_tracker(delta as usize)
+        } else {
+            self.decrease_tracker(delta.unsigned_abs());
+            false
+        }
+    }
+
+    #[inline]
+    fn track_resize(&self, old_layout: Layout, new_layout: Layout) {
+        if Self::is_paused() {
+            return;
+        }
+        // Sizes below the floor are not tracked, so resizing across it
+        // behaves like an allocation or deallocation.
+        let old_below = self.is_below_floor(old_layout.size());
+        let new_below = self.is_below_floor(new_layout.size());
+        let tracked = |size, below| if below { 0 } else { size };
+        let old_size = tracked(old_layout.size(), old_below);
+        let new_size = tracked(new_layout.size(), new_below);
+        if old_below && !new_below {
+            self.alloc_count.fetch_add(1, Self::ORDERING);
+            self.size_histogram[histogram::bucket(new_size)].fetch_add(1, Self::ORDERING);
+        } else if !old_below && new_below {
+            self.dealloc_count.fetch_add(1, Self::ORDERING);
+        }
+        if old_size != new_size {
+            self.shrink_size_class(old_size);
+            self.grow_size_class(new_size);
+        }
+        if new_size > old_size {
+            self.track_largest(new_size);
+            self.realloc_growth_bytes
+                .fetch_add(new_size - old_size, Self::ORDERING);
+            self.track_alloc(new_size - old_size);
+        } else if new_size < old_size {
+            // An old layout larger than the allocation, which is a bug of the
+            // caller, at worst clamps the tracker at 0.
+            self.realloc_shrink_bytes
+                .fetch_add(old_size - new_size, Self::ORDERING);
+            self.track_dealloc(old_size - new_size);
+        }
+        if self.track_alignment {
+            self.sub_alignment_overhead(alignment_padding(old_size, old_layout.align()));
+            self.add_alignment_overhead(alignment_padding(new_size, new_layout.align()));
+        }
     }
 }
 
-unsafe impl<'a, T: GlobalAlloc + 'a> GlobalAlloc for &'a PeakAlloc<T> {
+/// Converts a size for the signed tracker.
+///
+/// `Layout` guarantees sizes up to `isize::MAX`, so this only clamps sizes
+/// which would otherwise wrap around to a negative change, e.g. from a
+/// malformed layout.
+#[inline]
+fn signed(bytes: usize) -> isize {
+    bytes.min(isize::MAX as usize) as isize
+}
+
+/// The bytes needed to round `size` up to a multiple of `align`, which is a
+/// power of two.
+#[inline]
+fn alignment_padding(size: usize, align: usize) -> usize {
+    size.wrapping_neg() & (align - 1)
+}
+
+unsafe impl<'a, T: GlobalAlloc + 'a, const RELAXED: bool> GlobalAlloc
+    for &'a PeakAlloc<T, RELAXED>
+{
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
         (*self).alloc(layout)
     }
@@ -123,34 +1746,59 @@ unsafe impl<'a, T: GlobalAlloc + 'a> GlobalAlloc for &'a PeakAlloc<T> {
     }
 }
 
-unsafe impl<T: GlobalAlloc> GlobalAlloc for PeakAlloc<T> {
+unsafe impl<T: GlobalAlloc, const RELAXED: bool> GlobalAlloc for PeakAlloc<T, RELAXED> {
     #[inline]
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        self.track_alloc(layout.size());
-        self.inner.alloc(layout)
+        if self.refuses(layout.size()) {
+            return ptr::null_mut();
+        }
+        let ptr = self.inner.alloc(layout);
+        if !ptr.is_null() {
+            self.record_alloc(layout);
+            #[cfg(feature = "strict")]
+            self.strict_alloc(ptr, layout.size());
+        }
+        ptr
     }
 
     #[inline]
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
-        self.track_dealloc(layout.size());
+        self.record_dealloc(layout);
+        #[cfg(feature = "strict")]
+        self.strict_dealloc(ptr, layout.size());
         self.inner.dealloc(ptr, layout)
     }
 
     #[inline]
     unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
-        self.track_alloc(layout.size());
-        self.inner.alloc_zeroed(layout)
+        if self.refuses(layout.size()) {
+            return ptr::null_mut();
+        }
+        let ptr = self.inner.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            self.record_alloc(layout);
+            self.record_zeroed(layout.size());
+            #[cfg(feature = "strict")]
+            self.strict_alloc(ptr, layout.size());
+        }
+        ptr
     }
 
     #[inline]
     unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
-        if new_size > layout.size() {
-            let difference = new_size - layout.size();
-            self.track_alloc(difference);
-        } else if new_size < layout.size() {
-            let difference = layout.size() - new_size;
-            self.track_dealloc(difference);
-        }
-        self.inner.realloc(ptr, layout, new_size)
+        if new_size > layout.size() && self.refuses(new_size - layout.size()) {
+            return ptr::null_mut();
+        }
+        let new_ptr = self.inner.realloc(ptr, layout, new_size);
+        // On failure the original block is left untouched.
+        if !new_ptr.is_null() {
+            // The caller guarantees a valid layout for the new size.
+            let new_layout = Layout::from_size_align_unchecked(new_size, layout.align());
+            self.track_resize(layout, new_layout);
+            self.record_realloc(ptr, new_ptr, layout.size().min(new_size));
+            #[cfg(feature = "strict")]
+            self.strict_realloc(ptr, layout.size(), new_ptr, new_size);
+        }
+        new_ptr
     }
 }
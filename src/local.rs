@@ -0,0 +1,158 @@
+//! A single threaded allocator middleware without atomic operations.
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::cell::Cell;
+
+/// An allocator middleware which keeps track of peak memory consumption in
+/// plain cells instead of atomics, for strictly single threaded programs.
+///
+/// This removes all atomic operations from the allocation path, but the
+/// metrics get corrupted as soon as two threads allocate concurrently. It only
+/// tracks the peak, the memory in use, the total allocated bytes and the
+/// numbers of allocations and deallocations.
+///
+/// ```
+/// # extern crate peakmem_alloc;
+/// # use peakmem_alloc::PeakAllocLocal;
+/// # use std::alloc::System;
+/// // Safety: the program never spawns a thread.
+/// #[global_allocator]
+/// static GLOBAL: PeakAllocLocal<System> = unsafe { PeakAllocLocal::new(System) };
+/// # fn main() {}
+/// ```
+#[derive(Debug)]
+pub struct PeakAllocLocal<T> {
+    current: Cell<usize>,
+    peak: Cell<usize>,
+    total_allocated: Cell<usize>,
+    alloc_count: Cell<usize>,
+    dealloc_count: Cell<usize>,
+    inner: T,
+}
+
+// Safety: the constructor requires that the allocator is only ever used by a
+// single thread.
+unsafe impl<T: Sync> Sync for PeakAllocLocal<T> {}
+
+impl<T> PeakAllocLocal<T> {
+    /// Provides access to an instrumented instance of the given global
+    /// allocator, which doesn't synchronize its metrics.
+    ///
+    /// # Safety
+    ///
+    /// The allocator and its metrics must only be used by one thread at a
+    /// time. As the global allocator, this means the program must not spawn
+    /// any threads, including threads spawned by dependencies.
+    pub const unsafe fn new(inner: T) -> Self {
+        PeakAllocLocal {
+            current: Cell::new(0),
+            peak: Cell::new(0),
+            total_allocated: Cell::new(0),
+            alloc_count: Cell::new(0),
+            dealloc_count: Cell::new(0),
+            inner,
+        }
+    }
+
+    /// Resets the peak memory to the memory currently in use, and the total
+    /// allocated bytes to 0.
+    pub fn reset_peak_memory(&self) {
+        self.peak.set(self.current.get());
+        self.total_allocated.set(0);
+    }
+
+    /// Get the peak memory consumption
+    pub fn get_peak_memory(&self) -> usize {
+        self.peak.get()
+    }
+
+    /// Get the memory currently allocated through this allocator.
+    pub fn get_current_memory(&self) -> usize {
+        self.current.get()
+    }
+
+    /// Get the total number of bytes allocated, ignoring deallocations.
+    pub fn get_total_allocated(&self) -> usize {
+        self.total_allocated.get()
+    }
+
+    /// Get the number of allocations made.
+    pub fn get_alloc_count(&self) -> usize {
+        self.alloc_count.get()
+    }
+
+    /// Get the number of deallocations made.
+    pub fn get_dealloc_count(&self) -> usize {
+        self.dealloc_count.get()
+    }
+
+    #[inline]
+    fn track_alloc(&self, bytes: usize) {
+        if cfg!(feature = "disabled") {
+            return;
+        }
+        let current = self.current.get().saturating_add(bytes);
+        self.current.set(current);
+        self.peak.set(self.peak.get().max(current));
+        self.total_allocated
+            .set(self.total_allocated.get().saturating_add(bytes));
+    }
+
+    #[inline]
+    fn track_dealloc(&self, bytes: usize) {
+        if cfg!(feature = "disabled") {
+            return;
+        }
+        self.current.set(self.current.get().saturating_sub(bytes));
+    }
+
+    #[inline]
+    fn count(&self, counter: &Cell<usize>) {
+        if cfg!(feature = "disabled") {
+            return;
+        }
+        counter.set(counter.get().wrapping_add(1));
+    }
+}
+
+unsafe impl<T: GlobalAlloc> GlobalAlloc for PeakAllocLocal<T> {
+    #[inline]
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.inner.alloc(layout);
+        if !ptr.is_null() {
+            self.count(&self.alloc_count);
+            self.track_alloc(layout.size());
+        }
+        ptr
+    }
+
+    #[inline]
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.count(&self.dealloc_count);
+        self.track_dealloc(layout.size());
+        self.inner.dealloc(ptr, layout)
+    }
+
+    #[inline]
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.inner.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            self.count(&self.alloc_count);
+            self.track_alloc(layout.size());
+        }
+        ptr
+    }
+
+    #[inline]
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = self.inner.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            if new_size > layout.size() {
+                self.track_alloc(new_size - layout.size());
+            } else {
+                self.track_dealloc(layout.size() - new_size);
+            }
+        }
+        new_ptr
+    }
+}
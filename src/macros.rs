@@ -0,0 +1,71 @@
+/// Evaluates a block and returns the peak memory consumption during its
+/// execution.
+///
+/// Unlike [`PeakAlloc::measure`](crate::PeakAlloc::measure), the block is not a
+/// closure, so `return`, `?` and `break` work as usual. The value of the block
+/// is dropped, see [`measure_with_peak!`](crate::measure_with_peak!) to keep
+/// it.
+///
+/// ```
+/// # #[macro_use] extern crate peakmem_alloc;
+/// # use peakmem_alloc::PeakAlloc;
+/// # use std::alloc::System;
+/// # fn main() {
+/// let alloc = PeakAlloc::new(System);
+/// let peak = measure_peak!(alloc, {
+///     let _x: Vec<u8> = Vec::with_capacity(1_024);
+/// });
+/// # let _ = peak;
+/// # }
+/// ```
+#[macro_export]
+macro_rules! measure_peak {
+    ($alloc:expr, $body:block) => {{
+        let guard = $alloc.scope();
+        let _ = $body;
+        guard.finish()
+    }};
+}
+
+/// Evaluates a block and returns its value together with the peak memory
+/// consumption during its execution.
+///
+/// Like [`measure_peak!`](crate::measure_peak!), `return`, `?` and `break`
+/// work as usual inside the block.
+#[macro_export]
+macro_rules! measure_with_peak {
+    ($alloc:expr, $body:block) => {{
+        let guard = $alloc.scope();
+        let value = $body;
+        (value, guard.finish())
+    }};
+}
+
+/// Declares an instrumented global allocator.
+///
+/// `peak_global!(System)` wraps a unit struct in a static named `GLOBAL`. Other
+/// allocators, names and attributes are given in the form of a `static`
+/// declaration, without the `PeakAlloc` around the type and initializer:
+///
+/// ```
+/// # #[macro_use] extern crate peakmem_alloc;
+/// # use std::alloc::System;
+/// peak_global!(pub(crate) ALLOCATOR: System = System);
+///
+/// fn main() {
+///     let _x: Vec<u8> = Vec::with_capacity(1_024);
+///     # #[cfg(not(feature = "disabled"))]
+///     assert!(ALLOCATOR.get_peak_memory() >= 1_024);
+/// }
+/// ```
+#[macro_export]
+macro_rules! peak_global {
+    ($inner:ident) => {
+        $crate::peak_global!(GLOBAL: $inner = $inner);
+    };
+    ($(#[$attr:meta])* $vis:vis $name:ident: $inner:ty = $init:expr) => {
+        $(#[$attr])*
+        #[global_allocator]
+        $vis static $name: $crate::PeakAlloc<$inner> = $crate::PeakAlloc::new($init);
+    };
+}
@@ -0,0 +1,124 @@
+//! An allocator for testing the instrumentation.
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::alloc::System;
+
+/// An allocator which forwards to the system allocator, counting the calls it
+/// receives.
+///
+/// It can be wrapped to drive a [`PeakAlloc`](crate::PeakAlloc) in tests
+/// without installing it as the global allocator, and to check what reaches
+/// the wrapped allocator, by wrapping a reference to the mock. Failing
+/// allocations can be simulated with [`CountingMock::set_failing`].
+///
+/// ```
+/// # extern crate peakmem_alloc;
+/// # use peakmem_alloc::{CountingMock, PeakAlloc};
+/// # use std::alloc::{GlobalAlloc, Layout};
+/// # fn main() {
+/// let alloc = PeakAlloc::new(CountingMock::new());
+/// let layout = Layout::from_size_align(64, 8).unwrap();
+/// unsafe {
+///     let ptr = alloc.alloc(layout);
+///     alloc.dealloc(ptr, layout);
+/// }
+/// # #[cfg(not(feature = "disabled"))]
+/// assert_eq!(alloc.get_peak_memory(), 64);
+/// # }
+/// ```
+#[derive(Debug, Default)]
+pub struct CountingMock {
+    allocs: AtomicUsize,
+    deallocs: AtomicUsize,
+    reallocs: AtomicUsize,
+    failing: AtomicBool,
+}
+
+impl CountingMock {
+    /// Creates a mock which hasn't received any calls.
+    pub const fn new() -> Self {
+        CountingMock {
+            allocs: AtomicUsize::new(0),
+            deallocs: AtomicUsize::new(0),
+            reallocs: AtomicUsize::new(0),
+            failing: AtomicBool::new(false),
+        }
+    }
+
+    /// Get the number of calls to `alloc` and `alloc_zeroed`, including
+    /// failed ones.
+    pub fn allocs(&self) -> usize {
+        self.allocs.load(Ordering::SeqCst)
+    }
+
+    /// Get the number of calls to `dealloc`.
+    pub fn deallocs(&self) -> usize {
+        self.deallocs.load(Ordering::SeqCst)
+    }
+
+    /// Get the number of calls to `realloc`, including failed ones.
+    pub fn reallocs(&self) -> usize {
+        self.reallocs.load(Ordering::SeqCst)
+    }
+
+    /// Makes all subsequent allocations and reallocations fail by returning a
+    /// null pointer, until called with `false`.
+    pub fn set_failing(&self, failing: bool) {
+        self.failing.store(failing, Ordering::SeqCst);
+    }
+
+    fn is_failing(&self) -> bool {
+        self.failing.load(Ordering::SeqCst)
+    }
+}
+
+unsafe impl GlobalAlloc for CountingMock {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.allocs.fetch_add(1, Ordering::SeqCst);
+        if self.is_failing() {
+            return ptr::null_mut();
+        }
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.deallocs.fetch_add(1, Ordering::SeqCst);
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        self.allocs.fetch_add(1, Ordering::SeqCst);
+        if self.is_failing() {
+            return ptr::null_mut();
+        }
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        self.reallocs.fetch_add(1, Ordering::SeqCst);
+        if self.is_failing() {
+            return ptr::null_mut();
+        }
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+unsafe impl GlobalAlloc for &CountingMock {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        (*self).alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        (*self).dealloc(ptr, layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        (*self).alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        (*self).realloc(ptr, layout, new_size)
+    }
+}
@@ -0,0 +1,94 @@
+//! Rendering the metrics in the Prometheus text exposition format.
+
+use core::fmt::Write;
+use std::string::String;
+
+use PeakAlloc;
+
+impl<T, const RELAXED: bool> PeakAlloc<T, RELAXED> {
+    /// Renders the metrics in the Prometheus text exposition format, e.g. to
+    /// serve them from a `/metrics` endpoint.
+    ///
+    /// The gauges `peakmem_peak_bytes`, `peakmem_current_bytes` and
+    /// `peakmem_largest_allocation_bytes` and the counters
+    /// `peakmem_allocated_bytes_total`, `peakmem_deallocated_bytes_total`,
+    /// `peakmem_allocations_total` and `peakmem_deallocations_total` are
+    /// rendered with their `HELP` and `TYPE` comments. The name given with
+    /// [`PeakAlloc::with_label`] is attached as a `label` label.
+    ///
+    /// This allocates the returned `String`, so it must not be called from
+    /// inside the allocator.
+    #[cfg_attr(doc_cfg, doc(cfg(feature = "prometheus")))]
+    pub fn render_prometheus(&self) -> String {
+        let stats = self.clone_stats();
+        let metrics: [(&str, &str, &str, u64); 7] = [
+            (
+                "peak_bytes",
+                "gauge",
+                "Peak memory in use.",
+                stats.peak as u64,
+            ),
+            (
+                "current_bytes",
+                "gauge",
+                "Memory in use.",
+                stats.current as u64,
+            ),
+            (
+                "largest_allocation_bytes",
+                "gauge",
+                "Size of the largest allocation.",
+                stats.largest_allocation as u64,
+            ),
+            (
+                "allocated_bytes_total",
+                "counter",
+                "Bytes allocated.",
+                stats.total_allocated,
+            ),
+            (
+                "deallocated_bytes_total",
+                "counter",
+                "Bytes deallocated.",
+                stats.total_deallocated,
+            ),
+            (
+                "allocations_total",
+                "counter",
+                "Number of allocations.",
+                stats.alloc_count as u64,
+            ),
+            (
+                "deallocations_total",
+                "counter",
+                "Number of deallocations.",
+                stats.dealloc_count as u64,
+            ),
+        ];
+        let mut out = String::new();
+        for (name, kind, help, value) in metrics {
+            // Writing into a `String` never fails.
+            let _ = writeln!(out, "# HELP peakmem_{} {}", name, help);
+            let _ = writeln!(out, "# TYPE peakmem_{} {}", name, kind);
+            let _ = match self.label() {
+                Some(label) => writeln!(
+                    out,
+                    "peakmem_{}{{label=\"{}\"}} {}",
+                    name,
+                    escape(label),
+                    value
+                ),
+                None => writeln!(out, "peakmem_{} {}", name, value),
+            };
+        }
+        out
+    }
+}
+
+/// Escapes a label value, see the Prometheus text format.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
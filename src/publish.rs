@@ -0,0 +1,93 @@
+//! Pushing the metrics to all enabled integrations at once.
+
+use std::boxed::Box;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use PeakAlloc;
+#[cfg(feature = "tracing")]
+use Snapshot;
+
+type Observer = Box<dyn Fn(usize) + Send + Sync>;
+
+/// The observer registered with [`PeakAlloc::set_peak_observer`].
+pub(crate) type PeakObserver = Mutex<Option<Observer>>;
+
+#[cfg_attr(doc_cfg, doc(cfg(feature = "std")))]
+impl<T, const RELAXED: bool> PeakAlloc<T, RELAXED> {
+    /// Reads the metrics once and pushes them to every enabled integration:
+    /// first the gauges of the `metrics` feature, see
+    /// [`PeakAlloc::export_metrics`], then a `tracing` event at info level,
+    /// then the observer registered with [`PeakAlloc::set_peak_observer`].
+    ///
+    /// All integrations get the same values. The counters are read one after
+    /// another in the order of the fields of [`Snapshot`](crate::Snapshot),
+    /// without a lock, so concurrent allocations may make them slightly
+    /// inconsistent with each other, but the published peak is never below
+    /// the published memory in use. Only the reads are allocation free, the
+    /// integrations allocate, so this must be called from normal code, e.g. a
+    /// background thread.
+    pub fn publish(&self) {
+        let mut snapshot = self.snapshot();
+        // A concurrent allocation may have raised the tracker, but not yet the
+        // peak.
+        snapshot.peak = snapshot.peak.max(snapshot.current);
+        #[cfg(feature = "metrics")]
+        self.export_snapshot(&snapshot);
+        #[cfg(feature = "tracing")]
+        self.trace_snapshot(&snapshot);
+        self.observe_peak(snapshot.peak);
+    }
+
+    /// Registers an observer, which is invoked with the peak by
+    /// [`PeakAlloc::publish`] whenever the peak grew since the last call.
+    ///
+    /// Unlike the callback of [`PeakAlloc::set_peak_callback`], which runs
+    /// inside the allocator and must not allocate, the observer runs on the
+    /// thread calling `publish`, so it can capture state, allocate and e.g.
+    /// send the peak over a channel. It is called with a lock held, so it
+    /// must not register another observer. Replaces the previous observer.
+    pub fn set_peak_observer(&self, observer: Box<dyn Fn(usize) + Send + Sync>) {
+        let previous = self.lock_observer().replace(observer);
+        // Dropping a boxed closure may run arbitrary code, so not under the
+        // lock.
+        drop(previous);
+    }
+
+    /// Removes the observer registered with [`PeakAlloc::set_peak_observer`].
+    pub fn clear_peak_observer(&self) {
+        let previous = self.lock_observer().take();
+        drop(previous);
+    }
+
+    fn observe_peak(&self, peak: usize) {
+        // Only advanced when an observer runs, so one registered later still
+        // gets the peak reached before.
+        if let Some(observer) = &*self.lock_observer() {
+            if self.last_observed_peak.fetch_max(peak, Self::ORDERING) < peak {
+                observer(peak);
+            }
+        }
+    }
+
+    fn lock_observer(&self) -> MutexGuard<'_, Option<Observer>> {
+        self.peak_observer
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    #[cfg(feature = "tracing")]
+    fn trace_snapshot(&self, snapshot: &Snapshot) {
+        tracing::info!(
+            target: "peakmem_alloc",
+            label = self.label,
+            peak = snapshot.peak,
+            current = snapshot.current,
+            total_allocated = snapshot.total_allocated,
+            alloc_count = snapshot.alloc_count,
+            dealloc_count = snapshot.dealloc_count,
+            largest_allocation = snapshot.largest_allocation,
+            zeroed_bytes = snapshot.zeroed_bytes,
+            "memory metrics"
+        );
+    }
+}
@@ -0,0 +1,23 @@
+//! Hooks which run inside the allocator, but may allocate themselves, must not
+//! recurse into the allocator.
+
+use core::cell::Cell;
+
+thread_local! {
+    static BUSY: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Runs `f`, unless the current thread is already running a guarded hook.
+#[inline]
+pub(crate) fn guarded<R, F: FnOnce() -> R>(f: F) -> Option<R> {
+    BUSY.try_with(|busy| {
+        if busy.replace(true) {
+            return None;
+        }
+        let result = f();
+        busy.set(false);
+        Some(result)
+    })
+    .ok()
+    .flatten()
+}
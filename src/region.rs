@@ -0,0 +1,87 @@
+//! Named measurement regions.
+
+use core::ptr;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::sync::{Mutex, PoisonError};
+use std::vec::Vec;
+
+use scope::ScopeState;
+use PeakAlloc;
+
+struct Region {
+    alloc: usize,
+    name: &'static str,
+    state: ScopeState,
+}
+
+thread_local! {
+    static REGIONS: RefCell<Vec<Region>> = const { RefCell::new(Vec::new()) };
+}
+
+/// The highest peak observed per region name.
+pub(crate) type RegionPeaks = Mutex<BTreeMap<&'static str, usize>>;
+
+#[cfg_attr(doc_cfg, doc(cfg(feature = "std")))]
+impl<T, const RELAXED: bool> PeakAlloc<T, RELAXED> {
+    /// Starts measuring the peak memory consumption of a named region, which
+    /// ends with the matching call to [`PeakAlloc::pop_region`].
+    ///
+    /// Regions are kept on a stack per thread and behave like nested
+    /// [`PeakAlloc::scope`]s, so each thread has to pop its regions in reverse
+    /// order, and regions of different threads interfere with each other.
+    pub fn push_region(&self, name: &'static str) {
+        let alloc = self.region_key();
+        REGIONS.with(|regions| {
+            let mut regions = regions.borrow_mut();
+            // Grow the stack before the region starts, so it isn't measured.
+            regions.reserve(1);
+            regions.push(Region {
+                alloc,
+                name,
+                state: ScopeState::enter(self),
+            });
+        })
+    }
+
+    /// Ends the region started last on this thread by
+    /// [`PeakAlloc::push_region`] and returns its peak memory consumption.
+    ///
+    /// The peak is also added to the [`PeakAlloc::region_report`]. Returns
+    /// `None` if this thread has no open region on this allocator.
+    pub fn pop_region(&self) -> Option<usize> {
+        let this = self.region_key();
+        let region = REGIONS.with(|regions| {
+            let mut regions = regions.borrow_mut();
+            let index = regions.iter().rposition(|region| region.alloc == this)?;
+            Some(regions.remove(index))
+        })?;
+        let peak = region.state.peak(self);
+        region.state.exit(self);
+        let mut peaks = self
+            .region_peaks
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let entry = peaks.entry(region.name).or_insert(0);
+        *entry = (*entry).max(peak);
+        Some(peak)
+    }
+
+    /// Returns the highest peak memory consumption of each popped region,
+    /// sorted by name.
+    ///
+    /// Regions which were entered several times report the highest peak of
+    /// all their runs.
+    pub fn region_report(&self) -> Vec<(&'static str, usize)> {
+        let peaks = self
+            .region_peaks
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        peaks.iter().map(|(name, peak)| (*name, *peak)).collect()
+    }
+
+    /// Identifies the allocator, so regions of different instances don't mix.
+    fn region_key(&self) -> usize {
+        ptr::addr_of!(*self) as usize
+    }
+}
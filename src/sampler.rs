@@ -0,0 +1,171 @@
+//! A background thread recording the memory in use over time.
+
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use std::vec::Vec;
+
+use PeakAlloc;
+
+/// The most samples kept, the oldest ones are dropped first.
+const MAX_SAMPLES: usize = 4_096;
+
+/// A sampling thread started by [`PeakAlloc::spawn_sampler`].
+///
+/// Dropping the handle without calling [`SamplerHandle::stop`] also stops the
+/// thread, but discards the samples.
+#[derive(Debug)]
+pub struct SamplerHandle {
+    stop: Sender<()>,
+    thread: JoinHandle<()>,
+    shared: Arc<Mutex<Shared>>,
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    /// The time, the memory in use and the total allocated bytes of each
+    /// sample, oldest first.
+    samples: VecDeque<(Instant, usize, u64)>,
+    window: Option<Duration>,
+}
+
+impl Shared {
+    /// Appends a sample and drops the ones which left the window, or the
+    /// oldest one once there are [`MAX_SAMPLES`].
+    fn push(&mut self, sample: (Instant, usize, u64)) {
+        let since = self.window.and_then(|window| sample.0.checked_sub(window));
+        self.samples.push_back(sample);
+        while let Some(&(time, _, _)) = self.samples.front() {
+            let expired = since.is_some_and(|since| time < since);
+            if !expired && self.samples.len() <= MAX_SAMPLES {
+                break;
+            }
+            self.samples.pop_front();
+        }
+    }
+}
+
+impl SamplerHandle {
+    /// Only keeps the samples of the last `window`, for
+    /// [`SamplerHandle::recent_peak`] and the other accessors. Older samples
+    /// are dropped when the next one is taken. By default the last 4096
+    /// samples are kept.
+    pub fn set_peak_window(&self, window: Duration) {
+        self.lock().window = Some(window);
+    }
+
+    /// Returns the highest memory in use among the samples of the window set
+    /// with [`SamplerHandle::set_peak_window`].
+    ///
+    /// Unlike the peak of the allocator, this forgets about old spikes once
+    /// they leave the window. Spikes shorter than the sampling interval may
+    /// not be seen at all.
+    pub fn recent_peak(&self) -> usize {
+        let shared = self.lock();
+        let since = shared
+            .window
+            .and_then(|window| Instant::now().checked_sub(window));
+        shared
+            .samples
+            .iter()
+            .rev()
+            .take_while(|&&(time, _, _)| since.is_none_or(|since| time >= since))
+            .map(|&(_, bytes, _)| bytes)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Returns the highest allocation rate between two consecutive samples, in
+    /// bytes per second, or 0 before there are two samples.
+    ///
+    /// Compared to the peak, this tells a sudden spike apart from a gradual
+    /// climb. The rate is averaged over the sampling interval, so a burst
+    /// shorter than that shows up as a lower rate. Resetting the total
+    /// allocated bytes in between counts as no allocations.
+    pub fn peak_allocation_rate(&self) -> f64 {
+        let shared = self.lock();
+        shared
+            .samples
+            .iter()
+            .zip(shared.samples.iter().skip(1))
+            .filter_map(|(&(start, _, before), &(end, _, after))| {
+                let secs = end.duration_since(start).as_secs_f64();
+                (secs > 0.0).then(|| after.saturating_sub(before) as f64 / secs)
+            })
+            .fold(0.0, f64::max)
+    }
+
+    /// Stops the sampling thread and returns the kept samples of the memory
+    /// in use, oldest first.
+    pub fn stop(self) -> Vec<(Instant, usize)> {
+        // The thread may already be gone if it panicked, which `join` reports.
+        let _ = self.stop.send(());
+        self.thread.join().expect("sampler thread panicked");
+        let mut shared = self.shared.lock().unwrap_or_else(PoisonError::into_inner);
+        shared
+            .samples
+            .drain(..)
+            .map(|(time, bytes, _)| (time, bytes))
+            .collect()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Shared> {
+        self.shared.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg_attr(doc_cfg, doc(cfg(feature = "std")))]
+impl<T: Sync, const RELAXED: bool> PeakAlloc<T, RELAXED> {
+    /// Spawns a thread which records the memory in use every `interval`,
+    /// starting immediately, until [`SamplerHandle::stop`] is called.
+    ///
+    /// The thread only reads the counters, but storing the samples allocates,
+    /// which is included in the measurement if this is the global allocator.
+    /// The samples are kept in a ring buffer, see
+    /// [`SamplerHandle::set_peak_window`], so a long running sampler doesn't
+    /// grow without bounds.
+    pub fn spawn_sampler(&'static self, interval: Duration) -> SamplerHandle {
+        let (stop, stopped) = mpsc::channel();
+        let shared = Arc::new(Mutex::new(Shared::default()));
+        let samples = Arc::clone(&shared);
+        let thread = thread::spawn(move || loop {
+            let sample = (
+                Instant::now(),
+                self.get_current_memory(),
+                self.get_total_allocated_u64(),
+            );
+            samples
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .push(sample);
+            match stopped.recv_timeout(interval) {
+                Err(RecvTimeoutError::Timeout) => {}
+                Ok(()) | Err(RecvTimeoutError::Disconnected) => return,
+            }
+        });
+        SamplerHandle {
+            stop,
+            thread,
+            shared,
+        }
+    }
+}
+
+/// Writes samples, e.g. collected by [`PeakAlloc::spawn_sampler`], as CSV
+/// with the columns `elapsed_ms` and `bytes`.
+///
+/// The elapsed time is in whole milliseconds relative to the first sample.
+pub fn write_csv<W: Write>(samples: &[(Instant, usize)], mut w: W) -> io::Result<()> {
+    writeln!(w, "elapsed_ms,bytes")?;
+    let start = match samples.first() {
+        Some(&(start, _)) => start,
+        None => return Ok(()),
+    };
+    for &(time, bytes) in samples {
+        writeln!(w, "{},{}", time.duration_since(start).as_millis(), bytes)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,215 @@
+use PeakAlloc;
+
+/// Measures the peak memory consumption of a region, created by
+/// [`PeakAlloc::scope`].
+///
+/// The scope ends when the guard is dropped. Guards can be nested, each one
+/// reports the peak reached during its own lifetime, relative to the memory in
+/// use when it was created. Nested guards have to be dropped in reverse order
+/// of their creation, which also means guards created concurrently on
+/// different threads interfere with each other.
+///
+/// A guard can also report memory which is still in use when it is dropped,
+/// see [`PeakGuard::on_leak`], or hide its allocations from the enclosing
+/// guards, see [`PeakGuard::exclude`].
+#[derive(Debug)]
+pub struct PeakGuard<'a, T: 'a, const RELAXED: bool = false> {
+    alloc: &'a PeakAlloc<T, RELAXED>,
+    state: ScopeState,
+    on_leak: Option<fn(usize)>,
+    leak_threshold: usize,
+    excluded: bool,
+}
+
+impl<'a, T: 'a, const RELAXED: bool> PeakGuard<'a, T, RELAXED> {
+    pub(crate) fn new(alloc: &'a PeakAlloc<T, RELAXED>) -> Self {
+        PeakGuard {
+            alloc,
+            state: ScopeState::enter(alloc),
+            on_leak: None,
+            leak_threshold: 0,
+            excluded: false,
+        }
+    }
+
+    /// Excludes the scope from the measurements of the enclosing scopes, e.g.
+    /// to measure an algorithm without the fixture it sets up.
+    ///
+    /// When the guard is dropped, the enclosing scopes forget the peak reached
+    /// during its lifetime, and the memory it still has in use is not counted
+    /// towards their peaks while it stays in use. Which blocks are freed isn't
+    /// known, so once the memory in use drops below what it was when the guard
+    /// was dropped, the freed bytes are assumed to be the excluded ones, and
+    /// allocations afterwards are counted again. The guard itself still
+    /// reports its own peak.
+    pub fn exclude(mut self) -> Self {
+        self.excluded = true;
+        self
+    }
+
+    /// Invokes `cb` with the number of bytes still in use when the guard is
+    /// dropped, if more memory is in use than when it was created.
+    ///
+    /// This points at allocations which were never freed within the scope.
+    /// Memory allocated by other threads meanwhile is included as well. By
+    /// default nothing is reported.
+    pub fn on_leak(mut self, cb: fn(usize)) -> Self {
+        self.on_leak = Some(cb);
+        self
+    }
+
+    /// Only invokes the callback registered with [`PeakGuard::on_leak`] if
+    /// more than `bytes` are still in use. Defaults to 0.
+    pub fn leak_threshold(mut self, bytes: usize) -> Self {
+        self.leak_threshold = bytes;
+        self
+    }
+
+    /// Get the peak memory consumption since the guard was created.
+    pub fn peak(&self) -> usize {
+        self.state.peak(self.alloc)
+    }
+
+    /// Ends the scope and returns its peak memory consumption.
+    pub fn finish(self) -> usize {
+        self.peak()
+    }
+
+    /// The memory in use when the guard was created.
+    pub(crate) fn baseline(&self) -> usize {
+        self.state.baseline()
+    }
+}
+
+impl<'a, T: 'a, const RELAXED: bool> Drop for PeakGuard<'a, T, RELAXED> {
+    fn drop(&mut self) {
+        if self.excluded {
+            self.state.exit_excluded(self.alloc);
+        } else {
+            self.state.exit(self.alloc);
+        }
+        if let Some(cb) = self.on_leak {
+            let leaked = self.state.growth(self.alloc);
+            if leaked > self.leak_threshold {
+                cb(leaked);
+            }
+        }
+    }
+}
+
+/// The memory in use and the peaks at some point, taken by
+/// [`PeakAlloc::save`] to be restored later.
+///
+/// Dropping it without restoring keeps the current state.
+#[derive(Debug)]
+#[must_use = "the state is only restored by calling `restore`"]
+pub struct SavedState<'a, T: 'a, const RELAXED: bool = false> {
+    alloc: &'a PeakAlloc<T, RELAXED>,
+    tracker: isize,
+    peak: usize,
+    scope_peak: usize,
+    scope_excluded: usize,
+    scope_excluded_level: usize,
+}
+
+impl<'a, T: 'a, const RELAXED: bool> SavedState<'a, T, RELAXED> {
+    pub(crate) fn new(alloc: &'a PeakAlloc<T, RELAXED>) -> Self {
+        let ordering = PeakAlloc::<T, RELAXED>::ORDERING;
+        SavedState {
+            alloc,
+            tracker: alloc.peak_bytes_allocated_tracker.load(ordering),
+            peak: alloc.peak_bytes_allocated.load(ordering),
+            scope_peak: alloc.scope_peak.load(ordering),
+            scope_excluded: alloc.scope_excluded.load(ordering),
+            scope_excluded_level: alloc.scope_excluded_level.load(ordering),
+        }
+    }
+
+    /// Writes the saved memory in use and peaks back, as if nothing had
+    /// happened since the state was saved.
+    pub fn restore(self) {
+        let ordering = PeakAlloc::<T, RELAXED>::ORDERING;
+        self.alloc
+            .peak_bytes_allocated_tracker
+            .store(self.tracker, ordering);
+        self.alloc.peak_bytes_allocated.store(self.peak, ordering);
+        self.alloc.scope_peak.store(self.scope_peak, ordering);
+        self.alloc
+            .scope_excluded
+            .store(self.scope_excluded, ordering);
+        self.alloc
+            .scope_excluded_level
+            .store(self.scope_excluded_level, ordering);
+    }
+}
+
+/// The state of a strictly nested scope, shared by [`PeakGuard`] and named
+/// regions.
+///
+/// Excluded scopes leave memory in use which the enclosing scopes must not
+/// count. The allocator keeps the total of it which is still in use, and the
+/// scopes measure the memory in use without it.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ScopeState {
+    baseline: usize,
+    outer_peak: usize,
+}
+
+impl ScopeState {
+    pub(crate) fn enter<T, const RELAXED: bool>(alloc: &PeakAlloc<T, RELAXED>) -> Self {
+        let ordering = PeakAlloc::<T, RELAXED>::ORDERING;
+        let baseline = visible_memory(alloc);
+        let outer_peak = alloc.scope_peak.swap(baseline, ordering);
+        ScopeState {
+            baseline,
+            outer_peak,
+        }
+    }
+
+    /// The memory in use when the scope started.
+    pub(crate) fn baseline(&self) -> usize {
+        self.baseline
+    }
+
+    pub(crate) fn peak<T, const RELAXED: bool>(&self, alloc: &PeakAlloc<T, RELAXED>) -> usize {
+        alloc
+            .scope_peak
+            .load(PeakAlloc::<T, RELAXED>::ORDERING)
+            .saturating_sub(self.baseline)
+    }
+
+    /// The memory in use on top of the memory in use when the scope started.
+    pub(crate) fn growth<T, const RELAXED: bool>(&self, alloc: &PeakAlloc<T, RELAXED>) -> usize {
+        visible_memory(alloc).saturating_sub(self.baseline)
+    }
+
+    pub(crate) fn exit<T, const RELAXED: bool>(&self, alloc: &PeakAlloc<T, RELAXED>) {
+        // The enclosing scope has seen everything this scope has seen, plus
+        // whatever it saw before this scope was created.
+        alloc
+            .scope_peak
+            .fetch_max(self.outer_peak, PeakAlloc::<T, RELAXED>::ORDERING);
+    }
+
+    /// Exits the scope, hiding its peak and the memory it still has in use
+    /// from the enclosing scope.
+    pub(crate) fn exit_excluded<T, const RELAXED: bool>(&self, alloc: &PeakAlloc<T, RELAXED>) {
+        let ordering = PeakAlloc::<T, RELAXED>::ORDERING;
+        let visible = visible_memory(alloc);
+        let retained = visible.saturating_sub(self.baseline);
+        if retained > 0 {
+            alloc
+                .scope_excluded_level
+                .store(visible - retained, ordering);
+            alloc.scope_excluded.fetch_add(retained, ordering);
+        }
+        alloc.scope_peak.store(self.outer_peak, ordering);
+    }
+}
+
+/// The memory in use, without the memory excluded scopes left in use.
+fn visible_memory<T, const RELAXED: bool>(alloc: &PeakAlloc<T, RELAXED>) -> usize {
+    alloc
+        .get_current_memory()
+        .saturating_sub(alloc.scope_excluded.load(PeakAlloc::<T, RELAXED>::ORDERING))
+}
@@ -0,0 +1,63 @@
+use core::{
+    ptr,
+    sync::atomic::{AtomicIsize, Ordering},
+};
+
+/// The number of shards used by [`PeakAlloc::with_sharding`](crate::PeakAlloc::with_sharding).
+pub const SHARDS: usize = 16;
+
+/// The number of bytes a shard buffers before they are applied to the global
+/// tracker.
+pub const SHARD_FLUSH_THRESHOLD: usize = 64 * 1024;
+
+/// A counter of the changes not yet applied to the global tracker, on its own
+/// cache line.
+#[derive(Debug, Default)]
+#[repr(align(64))]
+pub(crate) struct Shard(AtomicIsize);
+
+impl Shard {
+    pub(crate) const fn new() -> Self {
+        Shard(AtomicIsize::new(0))
+    }
+
+    /// Adds `delta` to the shard. Returns everything buffered in the shard
+    /// once that exceeds the threshold.
+    #[inline]
+    pub(crate) fn buffer(&self, delta: isize, ordering: Ordering) -> Option<isize> {
+        let buffered = self.0.fetch_add(delta, ordering).wrapping_add(delta);
+        if buffered.unsigned_abs() < SHARD_FLUSH_THRESHOLD {
+            return None;
+        }
+        Some(self.take(ordering))
+    }
+
+    #[inline]
+    pub(crate) fn load(&self, ordering: Ordering) -> isize {
+        self.0.load(ordering)
+    }
+
+    /// Removes and returns everything buffered in the shard.
+    #[inline]
+    pub(crate) fn take(&self, ordering: Ordering) -> isize {
+        self.0.swap(0, ordering)
+    }
+
+    pub(crate) fn reset(&self, ordering: Ordering) {
+        self.0.store(0, ordering);
+    }
+}
+
+/// Picks the shard of the calling thread.
+///
+/// Thread locals may allocate on first use on some platforms, so the address of
+/// the stack is hashed instead. Threads have disjoint stacks, so this spreads
+/// them over the shards, and which shard a thread uses only affects
+/// contention, never correctness.
+#[inline]
+pub(crate) fn current_index() -> usize {
+    let marker = 0u8;
+    let page = ptr::addr_of!(marker) as usize >> 16;
+    let hash = page.wrapping_mul(0x9E37_79B9_7F4A_7C15_u64 as usize);
+    hash >> (usize::BITS - SHARDS.trailing_zeros())
+}
@@ -0,0 +1,180 @@
+//! Owned copies of the counters and their differences, for shipping them to a
+//! reporting thread and per operation accounting.
+
+use core::ops::{Add, Sub};
+
+use {PeakAlloc, Snapshot};
+
+/// The difference between two [`Snapshot`]s, computed by subtracting the
+/// earlier one from the later one.
+///
+/// The fields are signed, as e.g. the memory in use may have shrunk in
+/// between, or the peak after a reset. Deltas can be added to aggregate them
+/// over several operations.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SnapshotDelta {
+    /// The change of the peak memory consumption
+    pub peak: isize,
+    /// The change of the memory in use
+    pub current: isize,
+    /// The number of bytes allocated in between
+    pub total_allocated: isize,
+    /// The number of allocations in between
+    pub alloc_count: isize,
+    /// The number of deallocations in between
+    pub dealloc_count: isize,
+    /// The change of the size of the largest allocation
+    pub largest_allocation: isize,
+    /// The number of bytes allocated already zeroed in between
+    pub zeroed_bytes: isize,
+}
+
+/// The signed difference of two counters. Wraps around if it doesn't fit,
+/// which takes more than `isize::MAX` bytes or operations.
+#[inline]
+fn delta(after: usize, before: usize) -> isize {
+    after.wrapping_sub(before) as isize
+}
+
+impl Sub for Snapshot {
+    type Output = SnapshotDelta;
+
+    fn sub(self, earlier: Snapshot) -> SnapshotDelta {
+        SnapshotDelta {
+            peak: delta(self.peak, earlier.peak),
+            current: delta(self.current, earlier.current),
+            total_allocated: delta(self.total_allocated, earlier.total_allocated),
+            alloc_count: delta(self.alloc_count, earlier.alloc_count),
+            dealloc_count: delta(self.dealloc_count, earlier.dealloc_count),
+            largest_allocation: delta(self.largest_allocation, earlier.largest_allocation),
+            zeroed_bytes: delta(self.zeroed_bytes, earlier.zeroed_bytes),
+        }
+    }
+}
+
+impl Add for SnapshotDelta {
+    type Output = SnapshotDelta;
+
+    /// Adds the fields, saturating at the bounds of `isize`.
+    fn add(self, other: SnapshotDelta) -> SnapshotDelta {
+        SnapshotDelta {
+            peak: self.peak.saturating_add(other.peak),
+            current: self.current.saturating_add(other.current),
+            total_allocated: self.total_allocated.saturating_add(other.total_allocated),
+            alloc_count: self.alloc_count.saturating_add(other.alloc_count),
+            dealloc_count: self.dealloc_count.saturating_add(other.dealloc_count),
+            largest_allocation: self
+                .largest_allocation
+                .saturating_add(other.largest_allocation),
+            zeroed_bytes: self.zeroed_bytes.saturating_add(other.zeroed_bytes),
+        }
+    }
+}
+
+/// A copy of all the counters of a [`PeakAlloc`], taken by
+/// [`PeakAlloc::clone_stats`].
+///
+/// Unlike [`Snapshot`](crate::Snapshot), this includes every counter.
+/// Subtracting an earlier capture from a later one yields the difference of
+/// each field, e.g. the bytes allocated in between. The fields which can
+/// shrink, i.e. the peak, the memory in use, the largest allocation and the
+/// alignment overhead, saturate at 0, as does everything after a reset in
+/// between.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PeakStats {
+    /// The peak memory consumption
+    pub peak: usize,
+    /// The memory in use
+    pub current: usize,
+    /// The total number of bytes allocated
+    pub total_allocated: u64,
+    /// The total number of bytes deallocated
+    pub total_deallocated: u64,
+    /// The number of bytes requested by new allocations
+    pub initial_alloc_bytes: usize,
+    /// The number of bytes added by growing allocations with `realloc`
+    pub realloc_growth_bytes: usize,
+    /// The number of bytes allocated already zeroed
+    pub zeroed_bytes: usize,
+    /// The number of allocations
+    pub alloc_count: usize,
+    /// The number of deallocations
+    pub dealloc_count: usize,
+    /// The number of reallocations which moved the block
+    pub realloc_moved_count: usize,
+    /// The number of reallocations which resized the block in place
+    pub realloc_inplace_count: usize,
+    /// The number of bytes copied by reallocations which moved the block
+    pub realloc_copied_bytes: usize,
+    /// The size of the largest allocation
+    pub largest_allocation: usize,
+    /// The estimated memory wasted on alignment padding
+    pub alignment_overhead: usize,
+}
+
+impl Sub for PeakStats {
+    type Output = PeakStats;
+
+    fn sub(self, earlier: PeakStats) -> PeakStats {
+        PeakStats {
+            peak: self.peak.saturating_sub(earlier.peak),
+            current: self.current.saturating_sub(earlier.current),
+            total_allocated: self.total_allocated.saturating_sub(earlier.total_allocated),
+            total_deallocated: self
+                .total_deallocated
+                .saturating_sub(earlier.total_deallocated),
+            initial_alloc_bytes: self
+                .initial_alloc_bytes
+                .saturating_sub(earlier.initial_alloc_bytes),
+            realloc_growth_bytes: self
+                .realloc_growth_bytes
+                .saturating_sub(earlier.realloc_growth_bytes),
+            zeroed_bytes: self.zeroed_bytes.saturating_sub(earlier.zeroed_bytes),
+            alloc_count: self.alloc_count.saturating_sub(earlier.alloc_count),
+            dealloc_count: self.dealloc_count.saturating_sub(earlier.dealloc_count),
+            realloc_moved_count: self
+                .realloc_moved_count
+                .saturating_sub(earlier.realloc_moved_count),
+            realloc_inplace_count: self
+                .realloc_inplace_count
+                .saturating_sub(earlier.realloc_inplace_count),
+            realloc_copied_bytes: self
+                .realloc_copied_bytes
+                .saturating_sub(earlier.realloc_copied_bytes),
+            largest_allocation: self
+                .largest_allocation
+                .saturating_sub(earlier.largest_allocation),
+            alignment_overhead: self
+                .alignment_overhead
+                .saturating_sub(earlier.alignment_overhead),
+        }
+    }
+}
+
+impl<T, const RELAXED: bool> PeakAlloc<T, RELAXED> {
+    /// Copies all counters into an owned value, which can be sent to another
+    /// thread.
+    ///
+    /// Like [`PeakAlloc::snapshot`], each counter is read once, but not all at
+    /// the same instant.
+    pub fn clone_stats(&self) -> PeakStats {
+        PeakStats {
+            peak: self.get_peak_memory(),
+            current: self.get_current_memory(),
+            total_allocated: self.get_total_allocated_u64(),
+            total_deallocated: self.get_total_deallocated_u64(),
+            initial_alloc_bytes: self.get_initial_alloc_bytes(),
+            realloc_growth_bytes: self.get_realloc_growth_bytes(),
+            zeroed_bytes: self.get_zeroed_bytes(),
+            alloc_count: self.get_alloc_count(),
+            dealloc_count: self.get_dealloc_count(),
+            realloc_moved_count: self.get_realloc_moved_count(),
+            realloc_inplace_count: self.get_realloc_inplace_count(),
+            realloc_copied_bytes: self.get_realloc_copied_bytes(),
+            largest_allocation: self.get_largest_allocation(),
+            alignment_overhead: self.get_alignment_overhead(),
+        }
+    }
+}
@@ -0,0 +1,50 @@
+//! Counters in the shape of the `stats_alloc` crate, for comparing with
+//! measurements taken by it.
+
+use PeakAlloc;
+
+/// The counters of a [`PeakAlloc`] with the field names and semantics of
+/// `stats_alloc::Stats`, taken by [`PeakAlloc::as_region_stats`].
+///
+/// Like there, the bytes added by growing reallocations are included in
+/// `bytes_allocated`, the bytes removed by shrinking ones in
+/// `bytes_deallocated`, and `bytes_reallocated` is their net difference.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RegionStats {
+    /// The number of allocations
+    pub allocations: usize,
+    /// The number of deallocations
+    pub deallocations: usize,
+    /// The number of reallocations
+    pub reallocations: usize,
+    /// The total number of bytes allocated
+    pub bytes_allocated: usize,
+    /// The total number of bytes deallocated
+    pub bytes_deallocated: usize,
+    /// The net number of bytes added by reallocations
+    pub bytes_reallocated: isize,
+}
+
+#[cfg_attr(doc_cfg, doc(cfg(feature = "stats_alloc_compat")))]
+impl<T, const RELAXED: bool> PeakAlloc<T, RELAXED> {
+    /// Get the counters since the last reset in the shape of the `stats_alloc`
+    /// crate, see [`RegionStats`].
+    ///
+    /// The counters are read one after another, so they may not be consistent
+    /// with each other while other threads allocate.
+    pub fn as_region_stats(&self) -> RegionStats {
+        let growth = self.get_realloc_growth_bytes();
+        let shrink = self.get_realloc_shrink_bytes();
+        RegionStats {
+            allocations: self.get_alloc_count(),
+            deallocations: self.get_dealloc_count(),
+            reallocations: self
+                .get_realloc_moved_count()
+                .wrapping_add(self.get_realloc_inplace_count()),
+            bytes_allocated: self.get_total_allocated(),
+            bytes_deallocated: self.get_total_deallocated(),
+            bytes_reallocated: growth.wrapping_sub(shrink) as isize,
+        }
+    }
+}
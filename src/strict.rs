@@ -0,0 +1,122 @@
+//! Verifying that every deallocation matches an allocation.
+
+use core::sync::atomic::AtomicUsize;
+use std::collections::BTreeMap;
+use std::sync::{Mutex, PoisonError};
+
+use reentrancy;
+use PeakAlloc;
+
+/// The size of each allocation in use by its address, and the number of
+/// deallocations whose size didn't match.
+#[derive(Debug)]
+pub(crate) struct Outstanding {
+    sizes: Mutex<BTreeMap<usize, usize>>,
+    mismatches: AtomicUsize,
+}
+
+impl Outstanding {
+    pub(crate) const fn new() -> Self {
+        Outstanding {
+            sizes: Mutex::new(BTreeMap::new()),
+            mismatches: AtomicUsize::new(0),
+        }
+    }
+}
+
+#[cfg_attr(doc_cfg, doc(cfg(feature = "strict")))]
+impl<T, const RELAXED: bool> PeakAlloc<T, RELAXED> {
+    /// Get the number of allocations which were not freed yet, i.e. leaked if
+    /// the program is done with them.
+    ///
+    /// The `strict` feature records the address and size of every allocation
+    /// in a map guarded by a mutex, so every allocation and deallocation takes
+    /// a global lock and an `O(log n)` lookup, and the map needs about 50
+    /// bytes per allocation in use. It is meant for tests, not production.
+    /// The memory of the map is not included in the metrics.
+    ///
+    /// Allocations made before the last [`PeakAlloc::reset_all`] or while the
+    /// tracking was suspended are unknown, freeing them is not checked.
+    pub fn outstanding_allocations(&self) -> usize {
+        self.outstanding_sizes().0
+    }
+
+    /// Get the total size of the allocations which were not freed yet, see
+    /// [`PeakAlloc::outstanding_allocations`].
+    pub fn outstanding_bytes(&self) -> usize {
+        self.outstanding_sizes().1
+    }
+
+    /// Get the number of deallocations and reallocations whose layout had a
+    /// different size than the block was allocated with.
+    pub fn size_mismatches(&self) -> usize {
+        self.outstanding.mismatches.load(Self::ORDERING)
+    }
+
+    fn outstanding_sizes(&self) -> (usize, usize) {
+        let mut counts = (0, 0);
+        self.with_outstanding(|sizes| counts = (sizes.len(), sizes.values().sum()));
+        counts
+    }
+
+    pub(crate) fn strict_alloc(&self, ptr: *mut u8, bytes: usize) {
+        if Self::is_paused() {
+            return;
+        }
+        self.with_outstanding(|sizes| {
+            sizes.insert(ptr as usize, bytes);
+        });
+    }
+
+    pub(crate) fn strict_dealloc(&self, ptr: *mut u8, bytes: usize) {
+        if Self::is_paused() {
+            return;
+        }
+        self.with_outstanding(|sizes| self.check_size(sizes.remove(&(ptr as usize)), bytes));
+    }
+
+    pub(crate) fn strict_realloc(
+        &self,
+        old_ptr: *mut u8,
+        old_bytes: usize,
+        new_ptr: *mut u8,
+        bytes: usize,
+    ) {
+        if Self::is_paused() {
+            return;
+        }
+        self.with_outstanding(|sizes| {
+            let recorded = sizes.remove(&(old_ptr as usize));
+            if recorded.is_some() {
+                sizes.insert(new_ptr as usize, bytes);
+            }
+            self.check_size(recorded, old_bytes);
+        });
+    }
+
+    /// Forgets all outstanding allocations and size mismatches.
+    pub(crate) fn clear_outstanding(&self) {
+        self.outstanding.mismatches.store(0, Self::ORDERING);
+        self.with_outstanding(|sizes| sizes.clear());
+    }
+
+    fn check_size(&self, recorded: Option<usize>, bytes: usize) {
+        if recorded.is_some_and(|recorded| recorded != bytes) {
+            self.outstanding.mismatches.fetch_add(1, Self::ORDERING);
+        }
+    }
+
+    /// Runs `f` on the map of outstanding allocations. The map allocates and
+    /// frees its nodes while it is locked, which must neither recurse into the
+    /// map nor show up in the metrics.
+    fn with_outstanding<F: FnOnce(&mut BTreeMap<usize, usize>)>(&self, f: F) {
+        reentrancy::guarded(|| {
+            let _suspended = self.suspend();
+            f(&mut self
+                .outstanding
+                .sizes
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner))
+        });
+    }
+}
@@ -0,0 +1,54 @@
+//! Suspending the tracking on the current thread.
+
+use core::cell::Cell;
+use core::marker::PhantomData;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use PeakAlloc;
+
+thread_local! {
+    static SUSPENDED: Cell<usize> = const { Cell::new(0) };
+}
+
+/// The number of guards alive on all threads, so the thread local is only read
+/// while there are any.
+static GUARDS: AtomicUsize = AtomicUsize::new(0);
+
+/// Whether the tracking is suspended on the current thread.
+#[inline]
+pub(crate) fn is_suspended() -> bool {
+    GUARDS.load(Ordering::Relaxed) != 0 && SUSPENDED.try_with(Cell::get).unwrap_or(0) != 0
+}
+
+/// Suspends the tracking on the current thread until it is dropped, created by
+/// [`PeakAlloc::suspend`].
+#[derive(Debug)]
+pub struct SuspendGuard {
+    // The guard must be dropped on the thread which created it.
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Drop for SuspendGuard {
+    fn drop(&mut self) {
+        let _ = SUSPENDED.try_with(|suspended| suspended.set(suspended.get() - 1));
+        GUARDS.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg_attr(doc_cfg, doc(cfg(feature = "std")))]
+impl<T, const RELAXED: bool> PeakAlloc<T, RELAXED> {
+    /// Excludes the allocations of the current thread from all metrics until
+    /// the returned guard is dropped. Other threads are still tracked.
+    ///
+    /// Guards can be nested, the tracking resumes once all of them are
+    /// dropped. The suspension is kept in a thread local, so it applies to all
+    /// instances. Memory allocated while suspended and freed afterwards is
+    /// subtracted from the memory in use, which is clamped at 0.
+    pub fn suspend(&self) -> SuspendGuard {
+        GUARDS.fetch_add(1, Ordering::Relaxed);
+        let _ = SUSPENDED.try_with(|suspended| suspended.set(suspended.get() + 1));
+        SuspendGuard {
+            _not_send: PhantomData,
+        }
+    }
+}
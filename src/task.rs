@@ -0,0 +1,116 @@
+//! Measuring the peak memory consumption of a future across `.await` points.
+
+use core::fmt;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+#[cfg(feature = "tokio")]
+use std::cell::Cell;
+#[cfg(feature = "tokio")]
+use tokio::task::futures::TaskLocalFuture;
+
+use PeakAlloc;
+
+#[cfg(feature = "tokio")]
+tokio::task_local! {
+    /// The memory in use when the task of [`PeakAlloc::measure_tokio_task`]
+    /// was first polled.
+    static TASK_BASELINE: Cell<Option<usize>>;
+}
+
+/// A future measuring the peak memory consumption of the future it wraps,
+/// created by [`PeakAlloc::measure_task`].
+///
+/// It resolves to the output of the wrapped future and its peak memory
+/// consumption.
+#[must_use = "futures do nothing unless polled"]
+pub struct MeasureTask<'a, T: 'a, F, const RELAXED: bool = false> {
+    alloc: &'a PeakAlloc<T, RELAXED>,
+    future: F,
+    baseline: Option<usize>,
+    peak: usize,
+}
+
+impl<'a, T: 'a, F, const RELAXED: bool> fmt::Debug for MeasureTask<'a, T, F, RELAXED> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MeasureTask")
+            .field("baseline", &self.baseline)
+            .field("peak", &self.peak)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'a, T: 'a, F: Future, const RELAXED: bool> Future for MeasureTask<'a, T, F, RELAXED> {
+    type Output = (F::Output, usize);
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        // Safety: the wrapped future is never moved out of the pinned
+        // `MeasureTask`, so pinning it structurally is sound.
+        let this = unsafe { self.get_unchecked_mut() };
+        let future = unsafe { Pin::new_unchecked(&mut this.future) };
+        // The scope is exited on drop, so a panicking future doesn't leave the
+        // scopes of the thread behind.
+        let guard = this.alloc.scope();
+        let baseline = *this.baseline.get_or_insert_with(|| {
+            #[cfg(feature = "tokio")]
+            let _ = TASK_BASELINE.try_with(|task| {
+                if task.get().is_none() {
+                    task.set(Some(guard.baseline()));
+                }
+            });
+            guard.baseline()
+        });
+        let result = future.poll(cx);
+        let poll_peak = guard.baseline() + guard.finish();
+        this.peak = this.peak.max(poll_peak.saturating_sub(baseline));
+        result.map(|output| (output, this.peak))
+    }
+}
+
+impl<T, const RELAXED: bool> PeakAlloc<T, RELAXED> {
+    /// Wraps a future to measure its peak memory consumption, relative to the
+    /// memory in use when it is first polled.
+    ///
+    /// The state is kept in the returned future, so the measurement follows
+    /// the task across `.await` points, with any executor. Only the time the
+    /// task is being polled is observed, like a [`PeakGuard`](crate::PeakGuard)
+    /// around every poll. Other tasks still contaminate the measurement: the
+    /// memory they hold while the task is polled counts towards its peak, as
+    /// do their allocations when they run concurrently on other threads. The
+    /// measurement is only exact if nothing else runs meanwhile.
+    pub fn measure_task<F: Future>(&self, future: F) -> MeasureTask<'_, T, F, RELAXED> {
+        MeasureTask {
+            alloc: self,
+            future,
+            baseline: None,
+            peak: 0,
+        }
+    }
+
+    /// Like [`PeakAlloc::measure_task`], but also stores the baseline in a
+    /// tokio task-local, so code running inside the task can query the memory
+    /// consumption of the task with [`PeakAlloc::task_memory`].
+    ///
+    /// The same contamination applies: tasks running on the same thread are
+    /// only kept apart if the runtime polls a single task at a time, e.g. a
+    /// current thread runtime without other tasks.
+    #[cfg(feature = "tokio")]
+    #[cfg_attr(doc_cfg, doc(cfg(feature = "tokio")))]
+    pub fn measure_tokio_task<F: Future>(
+        &self,
+        future: F,
+    ) -> TaskLocalFuture<Cell<Option<usize>>, MeasureTask<'_, T, F, RELAXED>> {
+        TASK_BASELINE.scope(Cell::new(None), self.measure_task(future))
+    }
+
+    /// The memory in use above the baseline of the current task of
+    /// [`PeakAlloc::measure_tokio_task`].
+    ///
+    /// Returns `None` outside such a task.
+    #[cfg(feature = "tokio")]
+    #[cfg_attr(doc_cfg, doc(cfg(feature = "tokio")))]
+    pub fn task_memory(&self) -> Option<usize> {
+        let baseline = TASK_BASELINE.try_with(Cell::get).ok()??;
+        Some(self.get_current_memory().saturating_sub(baseline))
+    }
+}
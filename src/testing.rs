@@ -0,0 +1,58 @@
+//! Assertions for memory regression tests.
+
+use {HumanBytes, PeakAlloc};
+
+#[cfg_attr(doc_cfg, doc(cfg(feature = "testing")))]
+impl<T, const RELAXED: bool> PeakAlloc<T, RELAXED> {
+    /// Asserts that the peak memory consumption is below `limit` bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics with the peak and the limit in human readable units if the peak
+    /// reached the limit.
+    #[track_caller]
+    pub fn assert_peak_below(&self, limit: usize) {
+        let peak = self.get_peak_memory();
+        assert!(
+            peak < limit,
+            "peak memory consumption of {} ({} bytes) is not below the limit of {} ({} bytes)",
+            HumanBytes(peak),
+            peak,
+            HumanBytes(limit),
+            limit
+        );
+    }
+
+    /// Asserts that the peak memory consumption is within `tolerance` bytes of
+    /// `expected`, as measurements vary e.g. with the allocation strategy of a
+    /// collection.
+    ///
+    /// # Panics
+    ///
+    /// Panics with the peak, the expected peak and their signed difference in
+    /// bytes and human readable units if the peak is outside the tolerance.
+    #[track_caller]
+    pub fn assert_peak_eq_within(&self, expected: usize, tolerance: usize) {
+        let peak = self.get_peak_memory();
+        let (sign, difference) = if peak >= expected {
+            ('+', peak - expected)
+        } else {
+            ('-', expected - peak)
+        };
+        assert!(
+            difference <= tolerance,
+            "peak memory consumption of {} ({} bytes) differs from the expected {} ({} bytes) \
+             by {}{} ({}{} bytes), more than the tolerance of {} ({} bytes)",
+            HumanBytes(peak),
+            peak,
+            HumanBytes(expected),
+            expected,
+            sign,
+            HumanBytes(difference),
+            sign,
+            difference,
+            HumanBytes(tolerance),
+            tolerance
+        );
+    }
+}
@@ -0,0 +1,257 @@
+use core::{
+    alloc::GlobalAlloc,
+    alloc::Layout,
+    cell::Cell,
+    fmt, ptr,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use std::alloc::System;
+use std::sync::OnceLock;
+use std::thread::{self, ThreadId};
+use std::vec::Vec;
+
+use reentrancy;
+use suspend;
+use PeakAlloc;
+
+/// The number of threads whose memory in use is included in the breakdown.
+const MAX_THREADS: usize = 64;
+
+thread_local! {
+    static THREAD_CURRENT: Cell<usize> = const { Cell::new(0) };
+    static THREAD_PEAK: Cell<usize> = const { Cell::new(0) };
+    /// The address of the allocator the current thread is registered with,
+    /// and the index of its slot.
+    static SLOT: Cell<(usize, usize)> = const { Cell::new((0, usize::MAX)) };
+}
+
+/// The memory in use of one thread, published for the other threads.
+#[repr(align(64))]
+struct Slot {
+    id: OnceLock<ThreadId>,
+    current: AtomicUsize,
+    at_peak: AtomicUsize,
+}
+
+impl Slot {
+    const fn new() -> Self {
+        Slot {
+            id: OnceLock::new(),
+            current: AtomicUsize::new(0),
+            at_peak: AtomicUsize::new(0),
+        }
+    }
+}
+
+/// An allocator middleware which keeps track of the peak memory consumption of
+/// each thread, in addition to the global metrics of a [`PeakAlloc`].
+///
+/// Memory freed by another thread than the one which allocated it is
+/// attributed to the freeing thread, whose memory in use is clamped at 0.
+///
+/// The per thread metrics live in thread locals, so they are shared by all
+/// instances. This is only meant to be used as the global allocator.
+///
+/// To provide [`ThreadPeakAlloc::peak_breakdown`], every allocation which
+/// raises the global peak reads the memory in use of all registered threads,
+/// up to 64. While the peak keeps growing, e.g. when filling a large
+/// collection, this makes allocations slower the more threads there are.
+pub struct ThreadPeakAlloc<T> {
+    global: PeakAlloc<T>,
+    slots: [Slot; MAX_THREADS],
+    next_slot: AtomicUsize,
+}
+
+impl<T: fmt::Debug> fmt::Debug for ThreadPeakAlloc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ThreadPeakAlloc")
+            .field("global", &self.global)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T: Default> Default for ThreadPeakAlloc<T> {
+    fn default() -> Self {
+        ThreadPeakAlloc::new(T::default())
+    }
+}
+
+impl ThreadPeakAlloc<System> {
+    /// Provides access to an instrumented instance of the system allocator.
+    pub const fn system() -> Self {
+        ThreadPeakAlloc::new(System)
+    }
+}
+
+impl<T> ThreadPeakAlloc<T> {
+    /// Provides access to an instrumented instance of the given global
+    /// allocator.
+    pub const fn new(inner: T) -> Self {
+        ThreadPeakAlloc {
+            global: PeakAlloc::new(inner),
+            slots: [const { Slot::new() }; MAX_THREADS],
+            next_slot: AtomicUsize::new(0),
+        }
+    }
+
+    /// Get the metrics across all threads.
+    pub fn global(&self) -> &PeakAlloc<T> {
+        &self.global
+    }
+
+    /// Get the peak memory consumption of the current thread.
+    pub fn get_thread_peak(&self) -> usize {
+        THREAD_PEAK.with(Cell::get)
+    }
+
+    /// Get the memory currently allocated by the current thread.
+    pub fn get_thread_current(&self) -> usize {
+        THREAD_CURRENT.with(Cell::get)
+    }
+
+    /// Resets the peak memory of the current thread to the memory it currently
+    /// has allocated.
+    pub fn reset_thread_peak(&self) {
+        THREAD_PEAK.with(|peak| peak.set(self.get_thread_current()));
+    }
+
+    /// Get the memory each thread had in use when the global peak was last
+    /// reached, skipping threads which had none in use.
+    ///
+    /// The memory in use of the threads is read one after another, so the
+    /// breakdown is only approximate when threads allocate concurrently. Only
+    /// the first 64 threads which allocate are included.
+    pub fn peak_breakdown(&self) -> Vec<(ThreadId, usize)> {
+        self.registered()
+            .iter()
+            .filter_map(|slot| {
+                let at_peak = slot.at_peak.load(Ordering::SeqCst);
+                let id = slot.id.get()?;
+                (at_peak > 0).then_some((*id, at_peak))
+            })
+            .collect()
+    }
+
+    #[inline]
+    fn track_alloc(&self, bytes: usize, peak_before: usize) {
+        if cfg!(feature = "disabled") || suspend::is_suspended() {
+            return;
+        }
+        // Thread locals may already be gone while a thread shuts down.
+        let _ = THREAD_CURRENT.try_with(|current| {
+            let new = current.get().saturating_add(bytes);
+            current.set(new);
+            let _ = THREAD_PEAK.try_with(|peak| peak.set(peak.get().max(new)));
+            self.publish(new);
+        });
+        if self.global.get_peak_memory() > peak_before {
+            self.capture_breakdown();
+        }
+    }
+
+    #[inline]
+    fn track_dealloc(&self, bytes: usize) {
+        if cfg!(feature = "disabled") || suspend::is_suspended() {
+            return;
+        }
+        let _ = THREAD_CURRENT.try_with(|current| {
+            let new = current.get().saturating_sub(bytes);
+            current.set(new);
+            self.publish(new);
+        });
+    }
+
+    /// Publishes the memory in use of the current thread.
+    #[inline]
+    fn publish(&self, current: usize) {
+        if let Some(slot) = self.slot() {
+            slot.current.store(current, Ordering::SeqCst);
+        }
+    }
+
+    fn capture_breakdown(&self) {
+        for slot in self.registered() {
+            let current = slot.current.load(Ordering::SeqCst);
+            slot.at_peak.store(current, Ordering::SeqCst);
+        }
+    }
+
+    fn registered(&self) -> &[Slot] {
+        let claimed = self.next_slot.load(Ordering::SeqCst).min(MAX_THREADS);
+        &self.slots[..claimed]
+    }
+
+    /// Get the slot of the current thread, registering it first if needed.
+    #[inline]
+    fn slot(&self) -> Option<&Slot> {
+        let this = ptr::addr_of!(*self) as usize;
+        let (owner, index) = SLOT.try_with(Cell::get).ok()?;
+        if owner == this {
+            return self.slots.get(index);
+        }
+        // Getting the id of the thread may allocate.
+        let index = reentrancy::guarded(|| self.register())?;
+        let _ = SLOT.try_with(|slot| slot.set((this, index)));
+        self.slots.get(index)
+    }
+
+    #[cold]
+    fn register(&self) -> usize {
+        let id = thread::current().id();
+        if let Some(index) = self
+            .registered()
+            .iter()
+            .position(|slot| slot.id.get() == Some(&id))
+        {
+            return index;
+        }
+        let index = self.next_slot.fetch_add(1, Ordering::SeqCst);
+        if let Some(slot) = self.slots.get(index) {
+            let _ = slot.id.set(id);
+        }
+        // Threads beyond the limit keep an index without slot.
+        index
+    }
+}
+
+unsafe impl<T: GlobalAlloc> GlobalAlloc for ThreadPeakAlloc<T> {
+    #[inline]
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let peak = self.global.get_peak_memory();
+        let ptr = self.global.alloc(layout);
+        if !ptr.is_null() {
+            self.track_alloc(layout.size(), peak);
+        }
+        ptr
+    }
+
+    #[inline]
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.global.dealloc(ptr, layout);
+        self.track_dealloc(layout.size());
+    }
+
+    #[inline]
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let peak = self.global.get_peak_memory();
+        let ptr = self.global.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            self.track_alloc(layout.size(), peak);
+        }
+        ptr
+    }
+
+    #[inline]
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let peak = self.global.get_peak_memory();
+        let new_ptr = self.global.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            if new_size > layout.size() {
+                self.track_alloc(new_size - layout.size(), peak);
+            } else {
+                self.track_dealloc(layout.size() - new_size);
+            }
+        }
+        new_ptr
+    }
+}
@@ -0,0 +1,37 @@
+//! Recording when the peak was reached.
+
+use core::sync::atomic::AtomicU64;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use PeakAlloc;
+
+/// The point in time timestamps are measured from.
+static ORIGIN: OnceLock<Instant> = OnceLock::new();
+
+/// The nanoseconds since [`ORIGIN`] at which the peak was reached, plus one.
+/// 0 means no peak was reached since the last reset.
+pub(crate) type PeakTimestamp = AtomicU64;
+
+impl<T, const RELAXED: bool> PeakAlloc<T, RELAXED> {
+    /// Records the current time as the time of the peak.
+    #[inline]
+    pub(crate) fn stamp_peak(&self) {
+        // Neither reading the clock nor initializing the origin allocates.
+        let origin = *ORIGIN.get_or_init(Instant::now);
+        let nanos = origin.elapsed().as_nanos().min(u64::MAX as u128 - 1) as u64;
+        self.peak_timestamp.store(nanos + 1, Self::ORDERING);
+    }
+
+    /// Returns when the peak memory consumption was last raised, or `None` if
+    /// it wasn't raised since the last reset.
+    ///
+    /// Every new peak reads the clock, which is cheap, but not free.
+    #[cfg_attr(doc_cfg, doc(cfg(feature = "peak_timestamp")))]
+    pub fn peak_timestamp(&self) -> Option<Instant> {
+        match self.peak_timestamp.load(Self::ORDERING) {
+            0 => None,
+            stamp => Some(*ORIGIN.get()? + Duration::from_nanos(stamp - 1)),
+        }
+    }
+}
@@ -0,0 +1,59 @@
+#![cfg(not(feature = "disabled"))]
+
+extern crate peakmem_alloc;
+
+use peakmem_alloc::{ByteUnit, PeakAlloc};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+thread_local! {
+    static CALLS: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Counts the calls made by the current thread.
+struct CountingSystem;
+
+unsafe impl GlobalAlloc for CountingSystem {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        CALLS.with(|calls| calls.set(calls.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        CALLS.with(|calls| calls.set(calls.get() + 1));
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: PeakAlloc<CountingSystem> = PeakAlloc::new(CountingSystem);
+
+fn calls() -> usize {
+    CALLS.with(Cell::get)
+}
+
+#[test]
+fn reads_never_allocate() {
+    let data = vec![0u8; 1_024];
+    let before = calls();
+    let checkpoint = GLOBAL.checkpoint();
+    let guard = GLOBAL.scope();
+    let reads = [
+        GLOBAL.get_current_memory(),
+        GLOBAL.get_peak_memory(),
+        GLOBAL.peak_and_current().0,
+        GLOBAL.get_total_allocated(),
+        GLOBAL.get_alloc_count(),
+        GLOBAL.get_dealloc_count(),
+        GLOBAL.get_largest_allocation(),
+        GLOBAL.get_alignment_overhead(),
+        GLOBAL.get_current_in(ByteUnit::KiB) as usize,
+        GLOBAL.snapshot().peak,
+        GLOBAL.size_histogram()[0],
+        GLOBAL.histogram_iter().count(),
+        GLOBAL.peak_since(&checkpoint),
+        guard.finish(),
+    ];
+    assert_eq!(calls(), before);
+    assert!(reads[0] >= data.len());
+}
@@ -0,0 +1,71 @@
+#![cfg(all(feature = "nightly", not(feature = "disabled")))]
+#![cfg_attr(feature = "nightly", feature(allocator_api))]
+
+extern crate peakmem_alloc;
+
+use peakmem_alloc::{PeakAlloc, HISTOGRAM_BUCKETS, INSTRUMENTED_GLOBAL};
+use std::alloc::{Allocator, Layout};
+
+#[test]
+fn measure_single_vec() {
+    let alloc = PeakAlloc::system();
+    let mut vec: Vec<u8, _> = Vec::with_capacity_in(100, &alloc);
+    assert_eq!(alloc.get_current_memory(), 100);
+    vec.reserve_exact(200);
+    assert_eq!(alloc.get_current_memory(), 200);
+    vec.push(1);
+    vec.shrink_to_fit();
+    assert_eq!(alloc.get_current_memory(), 1);
+    drop(vec);
+    assert_eq!(alloc.get_current_memory(), 0);
+    assert_eq!(alloc.get_peak_memory(), 200);
+    assert_eq!(alloc.get_alloc_count(), 1);
+    assert_eq!(alloc.get_dealloc_count(), 1);
+}
+
+#[test]
+fn zeroed_allocations_and_growth() {
+    let alloc = PeakAlloc::system();
+    let layout = Layout::from_size_align(100, 8).unwrap();
+    let grown = Layout::from_size_align(300, 8).unwrap();
+    unsafe {
+        let ptr = alloc.allocate_zeroed(layout).unwrap().cast();
+        let ptr = alloc.grow_zeroed(ptr, layout, grown).unwrap().cast();
+        alloc.deallocate(ptr, grown);
+    }
+    assert_eq!(alloc.get_zeroed_bytes(), 300);
+}
+
+#[test]
+fn zero_sized_allocations() {
+    let alloc = PeakAlloc::system()
+        .with_size_class_tracking()
+        .with_alignment_tracking();
+    let layout = Layout::new::<()>();
+    unsafe {
+        let ptr = alloc.allocate(layout).unwrap().cast();
+        let zeroed = alloc.allocate_zeroed(layout).unwrap().cast();
+        alloc.deallocate(zeroed, layout);
+        alloc.deallocate(ptr, layout);
+    }
+    assert_eq!(alloc.get_alloc_count(), 2);
+    assert_eq!(alloc.get_dealloc_count(), 2);
+    assert_eq!(alloc.get_peak_memory(), 0);
+    assert_eq!(alloc.get_largest_allocation(), 0);
+    let histogram = alloc.size_histogram();
+    assert_eq!(histogram[0], 2);
+    assert_eq!(histogram.iter().sum::<usize>(), 2);
+    assert_eq!(alloc.peak_by_size_class(), [0; HISTOGRAM_BUCKETS]);
+    assert_eq!(alloc.histogram_summary().median, 1);
+    assert_eq!(alloc.most_common_alignment(), Some(1));
+}
+
+#[test]
+fn measure_with_instrumented_global() {
+    let mut vec = Vec::new_in(&INSTRUMENTED_GLOBAL);
+    vec.extend_from_slice(&[0u64; 16]);
+    assert_eq!(INSTRUMENTED_GLOBAL.get_current_memory(), 128);
+    drop(vec);
+    assert_eq!(INSTRUMENTED_GLOBAL.get_current_memory(), 0);
+    assert_eq!(INSTRUMENTED_GLOBAL.get_peak_memory(), 128);
+}
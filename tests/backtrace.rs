@@ -0,0 +1,44 @@
+#![cfg(all(feature = "backtrace", not(feature = "disabled")))]
+
+extern crate peakmem_alloc;
+
+use peakmem_alloc::PeakAlloc;
+use std::alloc::{GlobalAlloc, Layout};
+
+#[inline(never)]
+fn allocate_the_largest_block(alloc: &PeakAlloc<std::alloc::System>) {
+    let layout = Layout::from_size_align(1 << 20, 8).unwrap();
+    unsafe {
+        let ptr = alloc.alloc(layout);
+        alloc.dealloc(ptr, layout);
+    }
+}
+
+#[test]
+fn backtrace_of_largest_allocation() {
+    let alloc = PeakAlloc::system();
+    assert!(alloc.largest_allocation_backtrace().is_none());
+    allocate_the_largest_block(&alloc);
+    let small = Layout::from_size_align(64, 8).unwrap();
+    unsafe {
+        let ptr = alloc.alloc(small);
+        alloc.dealloc(ptr, small);
+    }
+    let backtrace = alloc.largest_allocation_backtrace().unwrap();
+    assert!(backtrace.contains("allocate_the_largest_block"));
+
+    alloc.clear_largest_allocation_backtrace();
+    assert!(alloc.largest_allocation_backtrace().is_none());
+    allocate_the_largest_block(&alloc);
+    alloc.reset_peak_memory();
+    assert!(alloc.largest_allocation_backtrace().is_none());
+}
+
+#[global_allocator]
+static GLOBAL: PeakAlloc<std::alloc::System> = PeakAlloc::system();
+
+#[test]
+fn backtrace_as_global_allocator() {
+    let _large: Vec<u8> = Vec::with_capacity(64 << 20);
+    assert!(GLOBAL.largest_allocation_backtrace().is_some());
+}
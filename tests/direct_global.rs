@@ -0,0 +1,19 @@
+#![cfg(all(feature = "std", not(feature = "disabled")))]
+
+extern crate peakmem_alloc;
+
+use peakmem_alloc::PeakAlloc;
+use std::alloc::System;
+
+#[global_allocator]
+static GLOBAL: PeakAlloc<System> = PeakAlloc::system();
+
+#[test]
+fn direct_global_allocator_tracks_allocations() {
+    let guard = GLOBAL.scope();
+    let vec: Vec<u8> = Vec::with_capacity(64 * 1024);
+    assert!(GLOBAL.get_current_memory() >= vec.capacity());
+    drop(vec);
+    assert!(guard.finish() >= 64 * 1024);
+    assert!(GLOBAL.get_alloc_count() > 0);
+}
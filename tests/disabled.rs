@@ -0,0 +1,20 @@
+#![cfg(feature = "disabled")]
+
+extern crate peakmem_alloc;
+
+use peakmem_alloc::PeakAlloc;
+use std::alloc::{GlobalAlloc, Layout};
+
+#[test]
+fn disabled_tracking_reports_zero() {
+    let alloc = PeakAlloc::system();
+    let layout = Layout::from_size_align(1_024, 8).unwrap();
+    unsafe {
+        let ptr = alloc.alloc(layout);
+        let ptr = alloc.realloc(ptr, layout, 2_048);
+        alloc.dealloc(ptr, Layout::from_size_align(2_048, 8).unwrap());
+    }
+    assert_eq!(alloc.snapshot(), PeakAlloc::system().snapshot());
+    assert_eq!(alloc.get_peak_memory(), 0);
+    assert_eq!(alloc.get_alloc_count(), 0);
+}
@@ -0,0 +1,34 @@
+#![cfg(feature = "std")]
+
+extern crate peakmem_alloc;
+
+use peakmem_alloc::PeakAlloc;
+use std::alloc::System;
+use std::env;
+use std::process::Command;
+
+#[global_allocator]
+static GLOBAL: PeakAlloc<System> = PeakAlloc::system();
+
+const CHILD: &str = "PEAKMEM_ALLOC_EXIT_CHILD";
+
+#[test]
+fn exit_report_child() {
+    if env::var_os(CHILD).is_some() {
+        GLOBAL.print_peak_on_exit();
+        GLOBAL.print_peak_on_exit();
+    }
+}
+
+#[test]
+fn peak_is_printed_on_exit() {
+    let output = Command::new(env::current_exe().unwrap())
+        .args(["--exact", "exit_report_child", "--test-threads", "1"])
+        .env(CHILD, "1")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(stderr.matches("peak memory consumption: ").count(), 1);
+    assert!(stderr.trim_end().ends_with(" bytes)"));
+}
@@ -0,0 +1,58 @@
+#![cfg(all(feature = "std", not(feature = "disabled")))]
+
+extern crate peakmem_alloc;
+
+use peakmem_alloc::PeakAlloc;
+use std::alloc::{GlobalAlloc, Layout};
+use std::env;
+use std::process::Command;
+use std::thread;
+
+const CHILD: &str = "PEAKMEM_ALLOC_FORBID_CHILD";
+
+fn alloc_and_free(alloc: &PeakAlloc<std::alloc::System>, size: usize) {
+    let layout = Layout::from_size_align(size, 8).unwrap();
+    unsafe {
+        let ptr = alloc.alloc(layout);
+        alloc.dealloc(ptr, layout);
+    }
+}
+
+#[test]
+fn forbid_child() {
+    if env::var_os(CHILD).is_none() {
+        return;
+    }
+    let alloc = PeakAlloc::system();
+    {
+        let _guard = alloc.forbid_allocations();
+        thread::scope(|s| {
+            s.spawn(|| alloc_and_free(&alloc, 100));
+        });
+    }
+    alloc_and_free(&alloc, 200);
+    let _outer = alloc.forbid_allocations();
+    drop(alloc.forbid_allocations());
+    alloc_and_free(&alloc, 1_234);
+    println!("not reached");
+}
+
+#[test]
+fn allocation_aborts_while_forbidden() {
+    let output = Command::new(env::current_exe().unwrap())
+        .args([
+            "--exact",
+            "forbid_child",
+            "--test-threads",
+            "1",
+            "--nocapture",
+        ])
+        .env(CHILD, "1")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("allocation of 1234 bytes while allocations are forbidden"));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(!stdout.contains("not reached"));
+}
@@ -0,0 +1,106 @@
+#![cfg(all(feature = "std", not(feature = "disabled")))]
+
+extern crate peakmem_alloc;
+
+use peakmem_alloc::{format_bytes, write_csv, ByteUnit, HumanBytes, PeakAlloc, Snapshot};
+use std::alloc::{GlobalAlloc, Layout};
+use std::time::{Duration, Instant};
+
+#[test]
+fn format_bytes_with_binary_prefixes() {
+    assert_eq!(format_bytes(0), "0 B");
+    assert_eq!(format_bytes(1_023), "1023 B");
+    assert_eq!(format_bytes(1_024), "1.00 KiB");
+    assert_eq!(format_bytes(512 * 1024), "512.00 KiB");
+    assert_eq!(format_bytes(1_536 * 1024), "1.50 MiB");
+    assert_eq!(format_bytes(1 << 30), "1.00 GiB");
+    assert_eq!(HumanBytes(2_048).to_string(), "2.00 KiB");
+}
+
+#[test]
+#[cfg(target_pointer_width = "64")]
+fn format_bytes_above_a_pebibyte() {
+    assert_eq!(format_bytes(3 << 50), "3.00 PiB");
+    assert_eq!(format_bytes(5 << 60), "5.00 EiB");
+    assert_eq!(format_bytes(usize::MAX), "16.00 EiB");
+}
+
+#[test]
+fn display_snapshot() {
+    let snapshot = Snapshot {
+        peak: 1 << 20,
+        current: 512 * 1024,
+        total_allocated: 3 << 20,
+        alloc_count: 3,
+        dealloc_count: 1,
+        largest_allocation: 256,
+        zeroed_bytes: 2_048,
+    };
+    assert_eq!(
+        snapshot.to_string(),
+        "peak: 1.00 MiB, current: 512.00 KiB, total allocated: 3.00 MiB, allocations: 3, \
+         deallocations: 1, largest allocation: 256 B, zeroed: 2.00 KiB"
+    );
+}
+
+#[test]
+fn unit_accessors() {
+    assert_eq!(ByteUnit::MiB.bytes(), 1_048_576);
+    assert_eq!(ByteUnit::GiB.convert(3 << 29), 1.5);
+
+    let alloc = PeakAlloc::system();
+    let layout = Layout::from_size_align(1_536 * 1024, 8).unwrap();
+    unsafe {
+        let ptr = alloc.alloc(layout);
+        assert_eq!(alloc.get_current_memory_kib(), 1_536.0);
+        assert_eq!(alloc.get_current_memory_mib(), 1.5);
+        assert_eq!(alloc.get_current_in(ByteUnit::B), 1_572_864.0);
+        alloc.dealloc(ptr, layout);
+    }
+    assert_eq!(alloc.get_current_memory_mib(), 0.0);
+    assert_eq!(alloc.get_peak_memory_kib(), 1_536.0);
+    assert_eq!(alloc.get_peak_memory_mib(), 1.5);
+    assert_eq!(alloc.get_peak_in(ByteUnit::GiB), 1.5 / 1024.0);
+}
+
+#[test]
+fn write_samples_as_csv() {
+    let start = Instant::now();
+    let samples = [
+        (start, 1_024),
+        (start + Duration::from_millis(5), 4_096),
+        (start + Duration::from_millis(1_250), 0),
+    ];
+    let mut csv = Vec::new();
+    write_csv(&samples, &mut csv).unwrap();
+    assert_eq!(
+        String::from_utf8(csv).unwrap(),
+        "elapsed_ms,bytes\n0,1024\n5,4096\n1250,0\n"
+    );
+
+    let mut csv = Vec::new();
+    write_csv(&[], &mut csv).unwrap();
+    assert_eq!(csv, b"elapsed_ms,bytes\n");
+}
+
+#[derive(Debug)]
+struct Inner;
+
+#[test]
+fn debug_prints_the_metrics() {
+    let alloc = PeakAlloc::new(Inner);
+    assert_eq!(
+        format!("{:?}", alloc),
+        "PeakAlloc { peak: 0, current: 0, total_allocated: 0, alloc_count: 0, \
+         dealloc_count: 0, largest_allocation: 0, zeroed_bytes: 0, realloc_moved_count: 0, \
+         realloc_inplace_count: 0, inner: Inner }"
+    );
+}
+
+#[test]
+fn debug_prints_the_label() {
+    static PARSER: PeakAlloc<Inner> = PeakAlloc::new(Inner).with_label("parser");
+    assert_eq!(PARSER.label(), Some("parser"));
+    assert!(format!("{:?}", PARSER).starts_with("PeakAlloc { label: \"parser\", peak: 0,"));
+    assert_eq!(PeakAlloc::new(Inner).label(), None);
+}
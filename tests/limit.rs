@@ -0,0 +1,19 @@
+#![cfg(all(feature = "std", not(feature = "disabled")))]
+
+extern crate peakmem_alloc;
+
+use peakmem_alloc::{PeakAlloc, INSTRUMENTED_SYSTEM};
+use std::alloc::System;
+
+#[global_allocator]
+static GLOBAL: &PeakAlloc<System> = &INSTRUMENTED_SYSTEM;
+
+#[test]
+fn limit_makes_large_vec_fail() {
+    GLOBAL.set_limit(GLOBAL.get_current_memory() + 1024 * 1024);
+    let mut vec: Vec<u8> = Vec::new();
+    let result = vec.try_reserve(10 * 1024 * 1024);
+    GLOBAL.set_limit(0);
+    assert!(result.is_err());
+    assert!(vec.try_reserve(10 * 1024 * 1024).is_ok());
+}
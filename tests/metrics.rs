@@ -0,0 +1,102 @@
+#![cfg(all(feature = "metrics", not(feature = "disabled")))]
+
+extern crate metrics;
+extern crate peakmem_alloc;
+
+use metrics::{
+    Counter, Gauge, GaugeFn, Histogram, Key, KeyName, Metadata, Recorder, SharedString, Unit,
+};
+use peakmem_alloc::PeakAlloc;
+use std::alloc::{GlobalAlloc, Layout};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+#[derive(Default)]
+struct Gauges(Mutex<HashMap<String, f64>>);
+
+struct NamedGauge(Arc<Gauges>, String);
+
+impl GaugeFn for NamedGauge {
+    fn increment(&self, _value: f64) {}
+
+    fn decrement(&self, _value: f64) {}
+
+    fn set(&self, value: f64) {
+        self.0 .0.lock().unwrap().insert(self.1.clone(), value);
+    }
+}
+
+struct GaugeRecorder(Arc<Gauges>);
+
+impl Recorder for GaugeRecorder {
+    fn describe_counter(&self, _key: KeyName, _unit: Option<Unit>, _description: SharedString) {}
+
+    fn describe_gauge(&self, _key: KeyName, _unit: Option<Unit>, _description: SharedString) {}
+
+    fn describe_histogram(&self, _key: KeyName, _unit: Option<Unit>, _description: SharedString) {}
+
+    fn register_counter(&self, _key: &Key, _metadata: &Metadata<'_>) -> Counter {
+        Counter::noop()
+    }
+
+    fn register_gauge(&self, key: &Key, _metadata: &Metadata<'_>) -> Gauge {
+        let mut name = key.name().to_string();
+        for label in key.labels() {
+            name += &format!("{{{}={}}}", label.key(), label.value());
+        }
+        Gauge::from_arc(Arc::new(NamedGauge(self.0.clone(), name)))
+    }
+
+    fn register_histogram(&self, _key: &Key, _metadata: &Metadata<'_>) -> Histogram {
+        Histogram::noop()
+    }
+}
+
+#[test]
+fn export_gauges() {
+    let gauges = Arc::new(Gauges::default());
+    let alloc = PeakAlloc::system();
+    let layout = Layout::from_size_align(1_024, 8).unwrap();
+    unsafe {
+        let a = alloc.alloc(layout);
+        let b = alloc.alloc(layout);
+        alloc.dealloc(b, layout);
+        metrics::with_local_recorder(&GaugeRecorder(gauges.clone()), || alloc.export_metrics());
+        alloc.dealloc(a, layout);
+    }
+    let gauges = gauges.0.lock().unwrap();
+    assert_eq!(gauges["peakmem_alloc.peak_bytes"], 2_048.0);
+    assert_eq!(gauges["peakmem_alloc.current_bytes"], 1_024.0);
+    assert_eq!(gauges["peakmem_alloc.allocations"], 2.0);
+    assert_eq!(gauges["peakmem_alloc.deallocations"], 1.0);
+}
+
+#[test]
+fn export_labelled_gauges() {
+    let gauges = Arc::new(Gauges::default());
+    let alloc = PeakAlloc::system().with_label("parser");
+    let layout = Layout::from_size_align(512, 8).unwrap();
+    unsafe {
+        let ptr = alloc.alloc(layout);
+        alloc.dealloc(ptr, layout);
+    }
+    metrics::with_local_recorder(&GaugeRecorder(gauges.clone()), || alloc.export_metrics());
+    let gauges = gauges.0.lock().unwrap();
+    assert_eq!(gauges["peakmem_alloc.peak_bytes{label=parser}"], 512.0);
+    assert!(!gauges.contains_key("peakmem_alloc.peak_bytes"));
+}
+
+#[test]
+fn publish_exports_gauges() {
+    let gauges = Arc::new(Gauges::default());
+    let alloc = PeakAlloc::system();
+    let layout = Layout::from_size_align(256, 8).unwrap();
+    unsafe {
+        let ptr = alloc.alloc(layout);
+        metrics::with_local_recorder(&GaugeRecorder(gauges.clone()), || alloc.publish());
+        alloc.dealloc(ptr, layout);
+    }
+    let gauges = gauges.0.lock().unwrap();
+    assert_eq!(gauges["peakmem_alloc.peak_bytes"], 256.0);
+    assert_eq!(gauges["peakmem_alloc.current_bytes"], 256.0);
+}
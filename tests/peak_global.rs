@@ -0,0 +1,15 @@
+#![cfg(not(feature = "disabled"))]
+
+#[macro_use]
+extern crate peakmem_alloc;
+
+use std::alloc::System;
+
+peak_global!(System);
+
+#[test]
+fn peak_global_declares_the_global_allocator() {
+    let guard = GLOBAL.scope();
+    let _x: Vec<u8> = Vec::with_capacity(4_096);
+    assert!(guard.finish() >= 4_096);
+}
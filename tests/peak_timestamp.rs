@@ -0,0 +1,27 @@
+#![cfg(all(feature = "peak_timestamp", not(feature = "disabled")))]
+
+extern crate peakmem_alloc;
+
+use peakmem_alloc::PeakAlloc;
+use std::alloc::{GlobalAlloc, Layout};
+use std::time::Instant;
+
+#[test]
+fn timestamp_of_the_last_new_peak() {
+    let alloc = PeakAlloc::system();
+    assert_eq!(alloc.peak_timestamp(), None);
+    let layout = Layout::from_size_align(1_024, 8).unwrap();
+    let before = Instant::now();
+    unsafe {
+        let ptr = alloc.alloc(layout);
+        let after = Instant::now();
+        let stamp = alloc.peak_timestamp().unwrap();
+        assert!(before <= stamp && stamp <= after);
+        alloc.dealloc(ptr, layout);
+        let ptr = alloc.alloc(layout);
+        assert_eq!(alloc.peak_timestamp(), Some(stamp));
+        alloc.dealloc(ptr, layout);
+    }
+    alloc.reset_peak_memory();
+    assert_eq!(alloc.peak_timestamp(), None);
+}
@@ -0,0 +1,49 @@
+#![cfg(all(feature = "prometheus", not(feature = "disabled")))]
+
+extern crate peakmem_alloc;
+
+use peakmem_alloc::PeakAlloc;
+use std::alloc::{GlobalAlloc, Layout};
+
+#[test]
+fn render_the_text_format() {
+    let alloc = PeakAlloc::system();
+    let layout = Layout::from_size_align(1_024, 8).unwrap();
+    unsafe {
+        let a = alloc.alloc(layout);
+        let b = alloc.alloc(layout);
+        alloc.dealloc(b, layout);
+        let rendered = alloc.render_prometheus();
+        alloc.dealloc(a, layout);
+        assert!(rendered.starts_with(
+            "# HELP peakmem_peak_bytes Peak memory in use.\n\
+             # TYPE peakmem_peak_bytes gauge\n\
+             peakmem_peak_bytes 2048\n"
+        ));
+        let samples: Vec<&str> = rendered
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .collect();
+        assert_eq!(
+            samples,
+            [
+                "peakmem_peak_bytes 2048",
+                "peakmem_current_bytes 1024",
+                "peakmem_largest_allocation_bytes 1024",
+                "peakmem_allocated_bytes_total 2048",
+                "peakmem_deallocated_bytes_total 1024",
+                "peakmem_allocations_total 2",
+                "peakmem_deallocations_total 1",
+            ]
+        );
+        assert!(rendered.contains("# TYPE peakmem_allocations_total counter\n"));
+    }
+}
+
+#[test]
+fn render_the_label() {
+    let alloc = PeakAlloc::system().with_label("db \"main\"");
+    assert!(alloc
+        .render_prometheus()
+        .contains("\npeakmem_current_bytes{label=\"db \\\"main\\\"\"} 0\n"));
+}
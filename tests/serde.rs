@@ -0,0 +1,25 @@
+#![cfg(feature = "serde")]
+
+extern crate peakmem_alloc;
+extern crate serde_json;
+
+use peakmem_alloc::Snapshot;
+
+#[test]
+fn snapshot_round_trips_through_json() {
+    let snapshot = Snapshot {
+        peak: 2_048,
+        current: 1_024,
+        total_allocated: 4_096,
+        alloc_count: 3,
+        dealloc_count: 2,
+        largest_allocation: 2_048,
+        zeroed_bytes: 1_024,
+    };
+    let json = serde_json::to_string(&snapshot).unwrap();
+    assert_eq!(
+        json,
+        r#"{"peak":2048,"current":1024,"total_allocated":4096,"alloc_count":3,"dealloc_count":2,"largest_allocation":2048,"zeroed_bytes":1024}"#
+    );
+    assert_eq!(serde_json::from_str::<Snapshot>(&json).unwrap(), snapshot);
+}
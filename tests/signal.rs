@@ -0,0 +1,41 @@
+#![cfg(all(target_os = "linux", feature = "std", not(feature = "disabled")))]
+
+extern crate peakmem_alloc;
+
+use peakmem_alloc::PeakAlloc;
+use std::alloc::System;
+use std::os::raw::c_int;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+extern "C" {
+    fn signal(signum: c_int, handler: extern "C" fn(c_int)) -> usize;
+    fn raise(sig: c_int) -> c_int;
+}
+
+const SIGUSR1: c_int = 10;
+const SIG_ERR: usize = usize::MAX;
+
+#[global_allocator]
+static GLOBAL: PeakAlloc<System> = PeakAlloc::system();
+
+static READ_PEAK: AtomicUsize = AtomicUsize::new(0);
+static READ_CURRENT: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" fn read_metrics(_: c_int) {
+    let snapshot = GLOBAL.snapshot();
+    let stats = GLOBAL.clone_stats();
+    let (peak, current) = GLOBAL.peak_and_current();
+    READ_PEAK.store(peak.max(snapshot.peak).max(stats.peak), Ordering::SeqCst);
+    READ_CURRENT.store(current, Ordering::SeqCst);
+}
+
+#[test]
+fn read_metrics_from_a_signal_handler() {
+    let data = vec![0u8; 4_096];
+    unsafe {
+        assert_ne!(signal(SIGUSR1, read_metrics), SIG_ERR);
+        assert_eq!(raise(SIGUSR1), 0);
+    }
+    assert!(READ_PEAK.load(Ordering::SeqCst) >= data.len());
+    assert!(READ_CURRENT.load(Ordering::SeqCst) >= data.len());
+}
@@ -0,0 +1,30 @@
+#![cfg(all(feature = "stats_alloc_compat", not(feature = "disabled")))]
+
+extern crate peakmem_alloc;
+
+use peakmem_alloc::{PeakAlloc, RegionStats};
+use std::alloc::{GlobalAlloc, Layout};
+
+#[test]
+fn counters_in_the_shape_of_stats_alloc() {
+    let alloc = PeakAlloc::system();
+    let layout = Layout::from_size_align(64, 8).unwrap();
+    unsafe {
+        let ptr = alloc.alloc(layout);
+        let ptr = alloc.realloc(ptr, layout, 256);
+        let ptr = alloc.realloc(ptr, Layout::from_size_align(256, 8).unwrap(), 32);
+        alloc.dealloc(ptr, Layout::from_size_align(32, 8).unwrap());
+    }
+    assert_eq!(
+        alloc.as_region_stats(),
+        RegionStats {
+            allocations: 1,
+            deallocations: 1,
+            reallocations: 2,
+            bytes_allocated: 256,
+            bytes_deallocated: 256,
+            bytes_reallocated: -32,
+        }
+    );
+    assert_eq!(alloc.get_realloc_shrink_bytes(), 224);
+}
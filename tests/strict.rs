@@ -0,0 +1,59 @@
+#![cfg(all(feature = "strict", not(feature = "disabled")))]
+
+extern crate peakmem_alloc;
+
+use peakmem_alloc::PeakAlloc;
+use std::alloc::{GlobalAlloc, Layout};
+
+#[test]
+fn outstanding_allocations_and_bytes() {
+    let alloc = PeakAlloc::system();
+    let small = Layout::from_size_align(16, 8).unwrap();
+    let large = Layout::from_size_align(1_024, 8).unwrap();
+    unsafe {
+        let first = alloc.alloc(small);
+        let second = alloc.alloc_zeroed(large);
+        assert_eq!(alloc.outstanding_allocations(), 2);
+        assert_eq!(alloc.outstanding_bytes(), 1_040);
+        // The map of outstanding allocations is not included in the metrics.
+        assert_eq!(alloc.get_current_memory(), 1_040);
+
+        let second = alloc.realloc(second, large, 2_048);
+        assert_eq!(alloc.outstanding_allocations(), 2);
+        assert_eq!(alloc.outstanding_bytes(), 2_064);
+
+        alloc.dealloc(first, small);
+        assert_eq!(alloc.outstanding_allocations(), 1);
+        alloc.dealloc(second, Layout::from_size_align(2_048, 8).unwrap());
+    }
+    assert_eq!(alloc.outstanding_allocations(), 0);
+    assert_eq!(alloc.outstanding_bytes(), 0);
+    assert_eq!(alloc.size_mismatches(), 0);
+}
+
+#[test]
+fn deallocation_with_the_wrong_size() {
+    let alloc = PeakAlloc::system();
+    let layout = Layout::from_size_align(64, 8).unwrap();
+    unsafe {
+        let ptr = alloc.alloc(layout);
+        // The system allocator doesn't use the size to free a block.
+        alloc.dealloc(ptr, Layout::from_size_align(32, 8).unwrap());
+    }
+    assert_eq!(alloc.size_mismatches(), 1);
+    assert_eq!(alloc.outstanding_allocations(), 0);
+}
+
+#[test]
+fn reset_forgets_outstanding_allocations() {
+    let alloc = PeakAlloc::system();
+    let layout = Layout::from_size_align(64, 8).unwrap();
+    unsafe {
+        let ptr = alloc.alloc(layout);
+        alloc.reset_all();
+        assert_eq!(alloc.outstanding_allocations(), 0);
+        // Freeing unknown blocks is not checked.
+        alloc.dealloc(ptr, Layout::from_size_align(32, 8).unwrap());
+    }
+    assert_eq!(alloc.size_mismatches(), 0);
+}
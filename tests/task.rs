@@ -0,0 +1,84 @@
+#![cfg(all(feature = "std", not(feature = "disabled")))]
+
+extern crate peakmem_alloc;
+
+use peakmem_alloc::PeakAlloc;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll, Waker};
+
+/// Holds 1_000 bytes in the first poll, and allocates another 500 bytes in
+/// the second one before freeing everything.
+struct TwoSteps<'a> {
+    alloc: &'a PeakAlloc<System>,
+    held: Option<*mut u8>,
+}
+
+const HELD: usize = 1_000;
+const TEMPORARY: usize = 500;
+
+fn layout(size: usize) -> Layout {
+    Layout::from_size_align(size, 8).unwrap()
+}
+
+impl<'a> Future for TwoSteps<'a> {
+    type Output = u32;
+
+    fn poll(mut self: Pin<&mut Self>, _cx: &mut Context) -> Poll<u32> {
+        unsafe {
+            match self.held.take() {
+                None => {
+                    self.held = Some(self.alloc.alloc(layout(HELD)));
+                    Poll::Pending
+                }
+                Some(held) => {
+                    let ptr = self.alloc.alloc(layout(TEMPORARY));
+                    self.alloc.dealloc(ptr, layout(TEMPORARY));
+                    self.alloc.dealloc(held, layout(HELD));
+                    Poll::Ready(42)
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn task_peak_across_await_points() {
+    let alloc = PeakAlloc::system();
+    let mut task = Box::pin(alloc.measure_task(TwoSteps {
+        alloc: &alloc,
+        held: None,
+    }));
+    let mut cx = Context::from_waker(Waker::noop());
+    assert!(task.as_mut().poll(&mut cx).is_pending());
+    // Another task running in between is not observed.
+    unsafe {
+        let ptr = alloc.alloc(layout(10_000));
+        alloc.dealloc(ptr, layout(10_000));
+    }
+    assert_eq!(
+        task.as_mut().poll(&mut cx),
+        Poll::Ready((42, HELD + TEMPORARY))
+    );
+    assert_eq!(alloc.get_peak_memory(), 11_000);
+}
+
+#[test]
+fn panicking_task_restores_the_enclosing_scope() {
+    let alloc = PeakAlloc::system();
+    let outer = alloc.scope();
+    unsafe {
+        let ptr = alloc.alloc(layout(HELD));
+        alloc.dealloc(ptr, layout(HELD));
+    }
+    let mut task = Box::pin(alloc.measure_task(std::future::poll_fn(|_| -> Poll<()> {
+        panic!("task failed")
+    })));
+    let mut cx = Context::from_waker(Waker::noop());
+    let polled = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let _ = task.as_mut().poll(&mut cx);
+    }));
+    assert!(polled.is_err());
+    assert_eq!(outer.finish(), HELD);
+}
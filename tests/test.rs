@@ -1,7 +1,19 @@
+#![cfg(feature = "std")]
+// Most tests assert tracked values, which the `disabled` feature turns off.
+#![cfg_attr(feature = "disabled", allow(dead_code, unused_imports))]
+
+#[macro_use]
 extern crate peakmem_alloc;
 
-use peakmem_alloc::{PeakAlloc, INSTRUMENTED_SYSTEM};
-use std::alloc::System;
+use peakmem_alloc::{
+    Admission, CountingMock, DynAlloc, PeakAlloc, PeakAllocLocal, PeakAllocRelaxed, PeakStats,
+    Snapshot, SnapshotDelta, HISTOGRAM_BUCKETS, INSTRUMENTED_SYSTEM, SHARD_FLUSH_THRESHOLD,
+    TOP_ALLOCATIONS,
+};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Barrier;
+use std::time::Duration;
 
 #[global_allocator]
 static GLOBAL: &PeakAlloc<System> = &INSTRUMENTED_SYSTEM;
@@ -15,3 +27,1697 @@ fn example_using_region() {
         GLOBAL.get_peak_memory()
     );
 }
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn current_memory_tracks_live_allocations() {
+    let alloc = PeakAlloc::system();
+    let layout = Layout::from_size_align(1_024, 8).unwrap();
+    unsafe {
+        let ptr = alloc.alloc(layout);
+        assert_eq!(alloc.get_current_memory(), 1_024);
+        alloc.dealloc(ptr, layout);
+    }
+    assert_eq!(alloc.get_current_memory(), 0);
+    assert_eq!(alloc.get_peak_memory(), 1_024);
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn peak_and_current_in_one_read() {
+    let alloc = PeakAlloc::system();
+    let layout = Layout::from_size_align(1_024, 8).unwrap();
+    unsafe {
+        let a = alloc.alloc(layout);
+        let b = alloc.alloc(layout);
+        alloc.dealloc(b, layout);
+        assert_eq!(alloc.peak_and_current(), (2_048, 1_024));
+        alloc.dealloc(a, layout);
+    }
+    assert_eq!(alloc.peak_and_current(), (2_048, 0));
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn total_allocated_ignores_deallocations() {
+    let alloc = PeakAlloc::system();
+    let layout = Layout::from_size_align(1_024, 8).unwrap();
+    unsafe {
+        for _ in 0..4 {
+            let ptr = alloc.alloc(layout);
+            alloc.dealloc(ptr, layout);
+        }
+        let ptr = alloc.alloc(layout);
+        let ptr = alloc.realloc(ptr, layout, 4_096);
+        let grown = Layout::from_size_align(4_096, 8).unwrap();
+        let ptr = alloc.realloc(ptr, grown, 512);
+        alloc.dealloc(ptr, Layout::from_size_align(512, 8).unwrap());
+    }
+    assert_eq!(alloc.get_total_allocated(), 5 * 1_024 + 3_072);
+    assert_eq!(alloc.get_peak_memory(), 4_096);
+    alloc.reset_peak_memory();
+    assert_eq!(alloc.get_total_allocated(), 0);
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn valley_is_the_minimum_after_deallocations() {
+    let alloc = PeakAlloc::system();
+    assert_eq!(alloc.get_min_memory(), 0);
+    let large = Layout::from_size_align(4_096, 8).unwrap();
+    let small = Layout::from_size_align(1_024, 8).unwrap();
+    unsafe {
+        let base = alloc.alloc(small);
+        let a = alloc.alloc(large);
+        assert_eq!(alloc.get_min_memory(), 0);
+        alloc.dealloc(a, large);
+        assert_eq!(alloc.get_min_memory(), 1_024);
+        let a = alloc.alloc(large);
+        let a = alloc.realloc(a, large, 2_048);
+        assert_eq!(alloc.get_min_memory(), 1_024);
+        alloc.reset_peak_memory();
+        assert_eq!(alloc.get_min_memory(), 3_072);
+        alloc.dealloc(a, Layout::from_size_align(2_048, 8).unwrap());
+        assert_eq!(alloc.get_min_memory(), 1_024);
+        alloc.dealloc(base, small);
+    }
+    assert_eq!(alloc.get_min_memory(), 0);
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn total_deallocated_counts_frees_and_shrinks() {
+    let alloc = PeakAlloc::system();
+    let layout = Layout::from_size_align(1_024, 8).unwrap();
+    unsafe {
+        let a = alloc.alloc(layout);
+        alloc.dealloc(a, layout);
+        let b = alloc.alloc(layout);
+        let b = alloc.realloc(b, layout, 4_096);
+        let grown = Layout::from_size_align(4_096, 8).unwrap();
+        let b = alloc.realloc(b, grown, 512);
+        assert_eq!(alloc.get_total_deallocated(), 1_024 + 3_584);
+        alloc.dealloc(b, Layout::from_size_align(512, 8).unwrap());
+    }
+    assert_eq!(alloc.get_total_deallocated(), 2 * 1_024 + 3_072);
+    assert_eq!(alloc.get_total_deallocated(), alloc.get_total_allocated());
+    alloc.reset_all();
+    assert_eq!(alloc.get_total_deallocated(), 0);
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn initial_and_growth_bytes() {
+    let alloc = PeakAlloc::system();
+    let layout = Layout::from_size_align(1_024, 8).unwrap();
+    unsafe {
+        let ptr = alloc.alloc(layout);
+        let ptr = alloc.realloc(ptr, layout, 4_096);
+        let grown = Layout::from_size_align(4_096, 8).unwrap();
+        let ptr = alloc.realloc(ptr, grown, 2_048);
+        alloc.dealloc(ptr, Layout::from_size_align(2_048, 8).unwrap());
+        let ptr = alloc.alloc_zeroed(layout);
+        alloc.dealloc(ptr, layout);
+    }
+    assert_eq!(alloc.get_initial_alloc_bytes(), 2_048);
+    assert_eq!(alloc.get_realloc_growth_bytes(), 3_072);
+    assert_eq!(
+        alloc.get_initial_alloc_bytes() + alloc.get_realloc_growth_bytes(),
+        alloc.get_total_allocated()
+    );
+    alloc.reset_peak_memory();
+    assert_eq!(alloc.get_initial_alloc_bytes(), 0);
+    assert_eq!(alloc.get_realloc_growth_bytes(), 0);
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn reset_peak_memory_keeps_live_memory() {
+    let alloc = PeakAlloc::system();
+    let live = Layout::from_size_align(10 << 20, 8).unwrap();
+    let more = Layout::from_size_align(1 << 20, 8).unwrap();
+    unsafe {
+        let a = alloc.alloc(live);
+        alloc.reset_peak_memory();
+        assert_eq!(alloc.get_peak_memory(), 10 << 20);
+        let b = alloc.alloc(more);
+        assert_eq!(alloc.get_peak_memory(), 11 << 20);
+        alloc.dealloc(b, more);
+        alloc.dealloc(a, live);
+    }
+    assert_eq!(alloc.get_current_memory(), 0);
+    alloc.reset_peak_memory();
+    assert_eq!(alloc.get_peak_memory(), 0);
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn allocations_below_the_floor_are_ignored() {
+    let alloc = PeakAlloc::new(Dangling);
+    alloc.set_min_tracked_size(1_024);
+    let small = Layout::from_size_align(64, 8).unwrap();
+    let large = Layout::from_size_align(4_096, 8).unwrap();
+    unsafe {
+        let a = alloc.alloc(small);
+        let b = alloc.alloc(large);
+        assert_eq!(alloc.get_current_memory(), 4_096);
+        assert_eq!(alloc.get_alloc_count(), 1);
+        let a = alloc.realloc(a, small, 2_048);
+        assert_eq!(alloc.get_current_memory(), 6_144);
+        let b = alloc.realloc(b, large, 512);
+        assert_eq!(alloc.get_current_memory(), 2_048);
+        alloc.dealloc(b, Layout::from_size_align(512, 8).unwrap());
+        alloc.dealloc(a, Layout::from_size_align(2_048, 8).unwrap());
+    }
+    assert_eq!(alloc.get_current_memory(), 0);
+    assert_eq!(alloc.get_peak_memory(), 6_144);
+    // Growing `a` past the floor counts as an allocation, and shrinking `b`
+    // below it as a deallocation.
+    assert_eq!(alloc.get_alloc_count(), 2);
+    assert_eq!(alloc.get_dealloc_count(), 2);
+    assert_eq!(alloc.size_histogram().iter().sum::<usize>(), 2);
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn total_allocated_beyond_the_pointer_width() {
+    let alloc = PeakAlloc::new(Dangling);
+    let huge = Layout::from_size_align(isize::MAX as usize, 1).unwrap();
+    // More than `u32::MAX` bytes on 32 bit targets, without overflowing a
+    // `u64` on 64 bit targets.
+    let rounds = if usize::BITS == 32 { 3 } else { 1 };
+    unsafe {
+        for _ in 0..rounds {
+            let ptr = alloc.alloc(huge);
+            alloc.dealloc(ptr, huge);
+        }
+    }
+    let total = rounds * isize::MAX as u64;
+    assert_eq!(alloc.get_total_allocated_u64(), total);
+    assert_eq!(
+        alloc.get_total_allocated(),
+        total.min(usize::MAX as u64) as usize
+    );
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn zeroed_bytes_are_counted_separately() {
+    let alloc = PeakAlloc::system();
+    let layout = Layout::from_size_align(1_024, 8).unwrap();
+    unsafe {
+        let a = alloc.alloc(layout);
+        let b = alloc.alloc_zeroed(layout);
+        let b = alloc.realloc(b, layout, 2_048);
+        alloc.dealloc(b, Layout::from_size_align(2_048, 8).unwrap());
+        alloc.dealloc(a, layout);
+    }
+    assert_eq!(alloc.get_zeroed_bytes(), 1_024);
+    assert_eq!(alloc.snapshot().zeroed_bytes, 1_024);
+    alloc.reset_peak_memory();
+    assert_eq!(alloc.get_zeroed_bytes(), 0);
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn reset_all_clears_every_metric() {
+    let alloc = PeakAlloc::system().with_alignment_tracking();
+    let layout = Layout::from_size_align(100, 64).unwrap();
+    alloc.push_region("region");
+    let leaked = unsafe {
+        let a = alloc.alloc_zeroed(layout);
+        let a = alloc.realloc(a, layout, 200);
+        let b = alloc.alloc(layout);
+        alloc.dealloc(a, Layout::from_size_align(200, 64).unwrap());
+        b
+    };
+    alloc.pop_region();
+    assert_ne!(alloc.snapshot().current, 0);
+
+    alloc.reset_all();
+    assert_eq!(alloc.peak_and_current(), (0, 0));
+    assert_eq!(alloc.get_total_allocated(), 0);
+    assert_eq!(alloc.get_initial_alloc_bytes(), 0);
+    assert_eq!(alloc.get_realloc_growth_bytes(), 0);
+    assert_eq!(alloc.get_zeroed_bytes(), 0);
+    assert_eq!(alloc.get_alloc_count(), 0);
+    assert_eq!(alloc.get_dealloc_count(), 0);
+    assert_eq!(alloc.get_realloc_moved_count(), 0);
+    assert_eq!(alloc.get_realloc_inplace_count(), 0);
+    assert_eq!(alloc.get_largest_allocation(), 0);
+    assert_eq!(alloc.get_alignment_overhead(), 0);
+    assert_eq!(alloc.size_histogram(), [0; HISTOGRAM_BUCKETS]);
+    assert_eq!(alloc.region_report(), vec![]);
+    assert!(!alloc.last_alloc_was_peak());
+
+    unsafe { alloc.dealloc(leaked, layout) };
+    assert_eq!(alloc.get_current_memory(), 0);
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn alloc_and_dealloc_counts() {
+    let alloc = PeakAlloc::system();
+    let layout = Layout::from_size_align(64, 8).unwrap();
+    unsafe {
+        let a = alloc.alloc(layout);
+        let b = alloc.alloc_zeroed(layout);
+        let b = alloc.realloc(b, layout, 128);
+        alloc.dealloc(a, layout);
+        alloc.reset_peak_memory();
+        assert_eq!(alloc.get_alloc_count(), 2);
+        assert_eq!(alloc.get_dealloc_count(), 1);
+        alloc.dealloc(b, Layout::from_size_align(128, 8).unwrap());
+    }
+    assert_eq!(alloc.get_dealloc_count(), 2);
+    alloc.reset_counts();
+    assert_eq!(alloc.get_alloc_count(), 0);
+    assert_eq!(alloc.get_dealloc_count(), 0);
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn snapshot_reads_all_metrics() {
+    let alloc = PeakAlloc::system();
+    let layout = Layout::from_size_align(256, 8).unwrap();
+    let before = alloc.snapshot();
+    unsafe {
+        let a = alloc.alloc(layout);
+        let b = alloc.alloc(layout);
+        alloc.dealloc(a, layout);
+        assert_eq!(
+            alloc.snapshot(),
+            Snapshot {
+                peak: 512,
+                current: 256,
+                total_allocated: 512,
+                alloc_count: 2,
+                dealloc_count: 1,
+                largest_allocation: 256,
+                zeroed_bytes: 0,
+            }
+        );
+        alloc.dealloc(b, layout);
+    }
+    assert_ne!(alloc.snapshot(), before);
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn subtract_and_add_snapshots() {
+    let alloc = PeakAlloc::system();
+    let layout = Layout::from_size_align(1_024, 8).unwrap();
+    let ptr = unsafe { alloc.alloc(layout) };
+    let before = alloc.snapshot();
+    unsafe {
+        let scratch = alloc.alloc_zeroed(layout);
+        alloc.dealloc(scratch, layout);
+        alloc.dealloc(ptr, layout);
+    }
+    let after = alloc.snapshot();
+    let freed = after - before;
+    assert_eq!(
+        freed,
+        SnapshotDelta {
+            peak: 1_024,
+            current: -1_024,
+            total_allocated: 1_024,
+            alloc_count: 1,
+            dealloc_count: 2,
+            largest_allocation: 0,
+            zeroed_bytes: 1_024,
+        }
+    );
+    assert_eq!((before - after).current, 1_024);
+    assert_eq!((before - after).alloc_count, -1);
+    assert_eq!(SnapshotDelta::default() + freed, freed);
+    let total = freed + freed;
+    assert_eq!(total.current, -2_048);
+    assert_eq!(total.alloc_count, 2);
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn clone_stats_and_subtract_captures() {
+    let alloc = PeakAlloc::system();
+    let layout = Layout::from_size_align(256, 8).unwrap();
+    let before = alloc.clone_stats();
+    unsafe {
+        let ptr = alloc.alloc(layout);
+        let ptr = alloc.realloc(ptr, layout, 1_024);
+        alloc.dealloc(ptr, Layout::from_size_align(1_024, 8).unwrap());
+    }
+    let after = std::thread::scope(|s| s.spawn(|| alloc.clone_stats()).join().unwrap());
+    assert_eq!(
+        after - before,
+        PeakStats {
+            peak: 1_024,
+            total_allocated: 1_024,
+            total_deallocated: 1_024,
+            initial_alloc_bytes: 256,
+            realloc_growth_bytes: 768,
+            alloc_count: 1,
+            dealloc_count: 1,
+            largest_allocation: 1_024,
+            realloc_moved_count: after.realloc_moved_count,
+            realloc_inplace_count: after.realloc_inplace_count,
+            realloc_copied_bytes: after.realloc_copied_bytes,
+            ..PeakStats::default()
+        }
+    );
+    assert_eq!(after.realloc_moved_count + after.realloc_inplace_count, 1);
+    alloc.reset_all();
+    assert_eq!(alloc.clone_stats() - after, PeakStats::default());
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn nested_scopes_report_local_peaks() {
+    let alloc = PeakAlloc::system();
+    let small = Layout::from_size_align(100, 8).unwrap();
+    let large = Layout::from_size_align(1_000, 8).unwrap();
+    unsafe {
+        let outer = alloc.scope();
+        let a = alloc.alloc(small);
+        {
+            let inner = alloc.scope();
+            let b = alloc.alloc(large);
+            alloc.dealloc(b, large);
+            assert_eq!(inner.finish(), 1_000);
+        }
+        let inner = alloc.scope();
+        let c = alloc.alloc(small);
+        assert_eq!(inner.peak(), 100);
+        drop(inner);
+        assert_eq!(outer.peak(), 1_100);
+        alloc.dealloc(c, small);
+        alloc.dealloc(a, small);
+        assert_eq!(outer.finish(), 1_100);
+    }
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn excluded_scopes_are_hidden_from_enclosing_scopes() {
+    let alloc = PeakAlloc::system();
+    let fixture = Layout::from_size_align(4_096, 8).unwrap();
+    let scratch = Layout::from_size_align(1_000, 8).unwrap();
+    let work = Layout::from_size_align(100, 8).unwrap();
+    unsafe {
+        let outer = alloc.scope();
+        let middle = alloc.scope();
+        let setup = alloc.scope().exclude();
+        let a = alloc.alloc(scratch);
+        let cache = alloc.alloc(fixture);
+        alloc.dealloc(a, scratch);
+        assert_eq!(setup.finish(), 5_096);
+        assert_eq!(middle.peak(), 0);
+
+        let b = alloc.alloc(work);
+        assert_eq!(middle.peak(), 100);
+        alloc.dealloc(b, work);
+        drop(middle);
+        assert_eq!(outer.peak(), 100);
+
+        // Once the cache is freed, allocations count again.
+        alloc.dealloc(cache, fixture);
+        assert_eq!(outer.peak(), 100);
+        let c = alloc.alloc(scratch);
+        alloc.dealloc(c, scratch);
+        assert_eq!(outer.finish(), 1_000);
+    }
+}
+
+static CONFIGURED_PEAK: AtomicUsize = AtomicUsize::new(0);
+
+fn record_configured_peak(peak: usize) {
+    CONFIGURED_PEAK.store(peak, Ordering::SeqCst);
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn configure_in_static_initializer() {
+    static CONFIGURED: PeakAlloc<System> = PeakAlloc::system()
+        .with_limit(8_192)
+        .with_peak_threshold(2_048)
+        .with_peak_callback(record_configured_peak);
+    let small = Layout::from_size_align(1_024, 8).unwrap();
+    let medium = Layout::from_size_align(2_048, 8).unwrap();
+    let large = Layout::from_size_align(16_384, 8).unwrap();
+    unsafe {
+        let a = CONFIGURED.alloc(small);
+        assert_eq!(CONFIGURED_PEAK.load(Ordering::SeqCst), 0);
+        assert!(CONFIGURED.alloc(large).is_null());
+        let b = CONFIGURED.alloc(medium);
+        assert_eq!(CONFIGURED_PEAK.load(Ordering::SeqCst), 3_072);
+        CONFIGURED.dealloc(b, medium);
+        CONFIGURED.dealloc(a, small);
+    }
+}
+
+static LEAKED: AtomicUsize = AtomicUsize::new(0);
+
+fn report_leak(leaked: usize) {
+    LEAKED.store(leaked, Ordering::SeqCst);
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn scope_reports_leaks_on_drop() {
+    let alloc = PeakAlloc::system();
+    let layout = Layout::from_size_align(256, 8).unwrap();
+    unsafe {
+        let freed = alloc.scope().on_leak(report_leak);
+        let ptr = alloc.alloc(layout);
+        alloc.dealloc(ptr, layout);
+        drop(freed);
+        assert_eq!(LEAKED.load(Ordering::SeqCst), 0);
+
+        let tolerated = alloc.scope().on_leak(report_leak).leak_threshold(256);
+        let first = alloc.alloc(layout);
+        drop(tolerated);
+        assert_eq!(LEAKED.load(Ordering::SeqCst), 0);
+
+        let leaking = alloc.scope().on_leak(report_leak);
+        let second = alloc.alloc(layout);
+        assert_eq!(leaking.finish(), 256);
+        assert_eq!(LEAKED.load(Ordering::SeqCst), 256);
+
+        alloc.dealloc(first, layout);
+        alloc.dealloc(second, layout);
+    }
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn reset_current_keeps_the_peak() {
+    let alloc = PeakAlloc::system();
+    let layout = Layout::from_size_align(1_024, 8).unwrap();
+    unsafe {
+        let cache = alloc.alloc(layout);
+        alloc.reset_current();
+        assert_eq!(alloc.get_current_memory(), 0);
+        assert_eq!(alloc.get_peak_memory(), 1_024);
+
+        let ptr = alloc.alloc(layout);
+        assert_eq!(alloc.get_current_memory(), 1_024);
+        assert_eq!(alloc.get_peak_memory(), 1_024);
+        alloc.dealloc(ptr, layout);
+        alloc.dealloc(cache, layout);
+    }
+    assert_eq!(alloc.get_current_memory(), 0);
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn measure_closure() {
+    let alloc = PeakAlloc::system();
+    let layout = Layout::from_size_align(2_048, 8).unwrap();
+    let (result, peak) = alloc.measure(|| unsafe {
+        let ptr = alloc.alloc(layout);
+        alloc.dealloc(ptr, layout);
+        42
+    });
+    assert_eq!(result, 42);
+    assert_eq!(peak, 2_048);
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn restore_a_saved_state() {
+    let alloc = PeakAlloc::system();
+    let small = Layout::from_size_align(1_024, 8).unwrap();
+    let large = Layout::from_size_align(8_192, 8).unwrap();
+    unsafe {
+        let outer = alloc.scope();
+        let kept = alloc.alloc(small);
+        let saved = alloc.save();
+        let noise = alloc.alloc(large);
+        alloc.dealloc(noise, large);
+        assert_eq!(alloc.get_peak_memory(), 9_216);
+        saved.restore();
+        assert_eq!(alloc.get_peak_memory(), 1_024);
+        assert_eq!(alloc.get_current_memory(), 1_024);
+        assert_eq!(outer.finish(), 1_024);
+        alloc.dealloc(kept, small);
+    }
+    assert_eq!(alloc.get_total_allocated(), 9_216);
+    drop(alloc.save());
+    assert_eq!(alloc.get_peak_memory(), 1_024);
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn measure_value_keeps_the_value_alive() {
+    let alloc = PeakAlloc::system();
+    let scratch = Layout::from_size_align(4_096, 8).unwrap();
+    let kept = Layout::from_size_align(1_024, 8).unwrap();
+    let (ptr, peak) = alloc.measure_value(|| unsafe {
+        let buffer = alloc.alloc(scratch);
+        let ptr = alloc.alloc(kept);
+        alloc.dealloc(buffer, scratch);
+        ptr
+    });
+    assert_eq!(peak, 5_120);
+    assert_eq!(alloc.get_current_memory(), 1_024);
+    unsafe { alloc.dealloc(ptr, kept) };
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn measure_each_item() {
+    let alloc = PeakAlloc::system();
+    let peaks = alloc.measure_each([1_024, 4_096, 512], |size| unsafe {
+        let layout = Layout::from_size_align(size, 8).unwrap();
+        let ptr = alloc.alloc(layout);
+        alloc.dealloc(ptr, layout);
+        size / 512
+    });
+    assert_eq!(peaks, [(2, 1_024), (8, 4_096), (1, 512)]);
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn foreign_deallocations_do_not_hide_peaks() {
+    let alloc = PeakAlloc::system();
+    let kb = Layout::from_size_align(1_024, 8).unwrap();
+    let foreign = Layout::from_size_align(2_048, 8).unwrap();
+    unsafe {
+        let ptr = alloc.alloc(kb);
+        assert_eq!(alloc.get_peak_memory(), 1_024);
+        alloc.dealloc(ptr, kb);
+        alloc.dealloc(System.alloc(foreign), foreign);
+        assert_eq!(alloc.get_current_memory(), 0);
+        let scope = alloc.scope();
+        let ptr = alloc.alloc(kb);
+        assert_eq!(scope.peak(), 1_024);
+        alloc.dealloc(ptr, kb);
+    }
+}
+
+/// Hands out dangling pointers without allocating, to drive the tracking with
+/// sizes which could not actually be allocated.
+struct Dangling;
+
+unsafe impl GlobalAlloc for Dangling {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        layout.align() as *mut u8
+    }
+
+    unsafe fn dealloc(&self, _ptr: *mut u8, _layout: Layout) {}
+
+    unsafe fn realloc(&self, ptr: *mut u8, _layout: Layout, _new_size: usize) -> *mut u8 {
+        ptr
+    }
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn realloc_shrink_with_inflated_layout_saturates() {
+    let alloc = PeakAlloc::new(Dangling);
+    let kb = Layout::from_size_align(1_024, 8).unwrap();
+    let inflated = Layout::from_size_align(1 << 20, 8).unwrap();
+    unsafe {
+        let ptr = alloc.alloc(kb);
+        let ptr = alloc.realloc(ptr, inflated, 512);
+        assert_eq!(alloc.get_current_memory(), 0);
+        assert_eq!(alloc.get_peak_memory(), 1_024);
+        alloc.dealloc(ptr, Layout::from_size_align(512, 8).unwrap());
+
+        let ptr = alloc.alloc(kb);
+        let other = alloc.alloc(kb);
+        assert_eq!(alloc.get_current_memory(), 2_048);
+        assert_eq!(alloc.get_peak_memory(), 2_048);
+        alloc.dealloc(other, kb);
+        alloc.dealloc(ptr, kb);
+    }
+    assert_eq!(alloc.get_current_memory(), 0);
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn tracker_saturates_instead_of_overflowing() {
+    let alloc = PeakAlloc::new(Dangling);
+    let huge = Layout::from_size_align(isize::MAX as usize, 1).unwrap();
+    unsafe {
+        let a = alloc.alloc(huge);
+        let b = alloc.alloc(huge);
+        assert_eq!(alloc.get_current_memory(), isize::MAX as usize);
+        assert_eq!(alloc.get_peak_memory(), isize::MAX as usize);
+        alloc.dealloc(a, huge);
+        alloc.dealloc(b, huge);
+    }
+    assert_eq!(alloc.get_current_memory(), 0);
+    assert_eq!(alloc.get_peak_memory(), isize::MAX as usize);
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn grow_to_the_largest_layout() {
+    let alloc = PeakAlloc::new(Dangling).with_sharding();
+    let byte = Layout::from_size_align(1, 1).unwrap();
+    let huge = Layout::from_size_align(isize::MAX as usize, 1).unwrap();
+    unsafe {
+        let ptr = alloc.alloc(byte);
+        let ptr = alloc.realloc(ptr, byte, huge.size());
+        assert_eq!(alloc.get_current_memory(), isize::MAX as usize);
+        let ptr = alloc.realloc(ptr, huge, 1);
+        assert_eq!(alloc.get_current_memory(), 1);
+        alloc.dealloc(ptr, byte);
+    }
+    assert_eq!(alloc.get_current_memory(), 0);
+    assert_eq!(alloc.get_peak_memory(), isize::MAX as usize);
+}
+
+static REPORTED_PEAK: AtomicUsize = AtomicUsize::new(0);
+
+fn record_peak(peak: usize) {
+    REPORTED_PEAK.store(peak, Ordering::SeqCst);
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn peak_callback_fires_on_new_peak() {
+    let alloc = PeakAlloc::system();
+    let layout = Layout::from_size_align(512, 8).unwrap();
+    alloc.set_peak_callback(record_peak);
+    unsafe {
+        let a = alloc.alloc(layout);
+        assert_eq!(REPORTED_PEAK.load(Ordering::SeqCst), 512);
+        alloc.dealloc(a, layout);
+        REPORTED_PEAK.store(0, Ordering::SeqCst);
+        let a = alloc.alloc(layout);
+        assert_eq!(REPORTED_PEAK.load(Ordering::SeqCst), 0);
+        alloc.clear_peak_callback();
+        let b = alloc.alloc(layout);
+        assert_eq!(REPORTED_PEAK.load(Ordering::SeqCst), 0);
+        alloc.dealloc(a, layout);
+        alloc.dealloc(b, layout);
+    }
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn relaxed_allocator_tracks_peak() {
+    let alloc = PeakAllocRelaxed::relaxed(System);
+    let layout = Layout::from_size_align(128, 8).unwrap();
+    unsafe {
+        let a = alloc.alloc(layout);
+        let b = alloc.alloc(layout);
+        alloc.dealloc(a, layout);
+        alloc.dealloc(b, layout);
+    }
+    assert_eq!(alloc.get_peak_memory(), 256);
+    assert_eq!(alloc.get_current_memory(), 0);
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn sharded_tracking_applies_buffered_changes() {
+    let alloc = PeakAlloc::new(Dangling).with_sharding();
+    let small = Layout::from_size_align(1_024, 8).unwrap();
+    let large = Layout::from_size_align(SHARD_FLUSH_THRESHOLD, 8).unwrap();
+    unsafe {
+        let a = alloc.alloc(small);
+        assert_eq!(alloc.get_current_memory(), 1_024);
+        let b = alloc.alloc(large);
+        assert_eq!(alloc.get_peak_memory(), SHARD_FLUSH_THRESHOLD + 1_024);
+        alloc.dealloc(b, large);
+        alloc.dealloc(a, small);
+    }
+    assert_eq!(alloc.get_current_memory(), 0);
+    assert_eq!(alloc.get_total_allocated(), SHARD_FLUSH_THRESHOLD + 1_024);
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn take_peak_restarts_at_current_memory() {
+    let alloc = PeakAlloc::system();
+    let layout = Layout::from_size_align(1_000, 8).unwrap();
+    unsafe {
+        let a = alloc.alloc(layout);
+        let b = alloc.alloc(layout);
+        alloc.dealloc(b, layout);
+        assert_eq!(alloc.take_peak(), 2_000);
+        assert_eq!(alloc.get_peak_memory(), 1_000);
+        alloc.dealloc(a, layout);
+    }
+    assert_eq!(alloc.take_peak(), 1_000);
+    assert_eq!(alloc.get_peak_memory(), 0);
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn take_snapshot_starts_a_new_interval() {
+    let alloc = PeakAlloc::system();
+    let layout = Layout::from_size_align(1_000, 8).unwrap();
+    unsafe {
+        let a = alloc.alloc_zeroed(layout);
+        let b = alloc.alloc(layout);
+        alloc.dealloc(b, layout);
+        assert_eq!(
+            alloc.take_snapshot(),
+            Snapshot {
+                peak: 2_000,
+                current: 1_000,
+                total_allocated: 2_000,
+                alloc_count: 2,
+                dealloc_count: 1,
+                largest_allocation: 1_000,
+                zeroed_bytes: 1_000,
+            }
+        );
+        assert_eq!(alloc.get_total_deallocated(), 0);
+        alloc.dealloc(a, layout);
+    }
+    assert_eq!(
+        alloc.take_snapshot(),
+        Snapshot {
+            peak: 1_000,
+            current: 0,
+            total_allocated: 0,
+            alloc_count: 0,
+            dealloc_count: 1,
+            largest_allocation: 0,
+            zeroed_bytes: 0,
+        }
+    );
+    assert_eq!(alloc.get_initial_alloc_bytes(), 0);
+    assert_eq!(alloc.get_total_deallocated(), 0);
+    assert_eq!(alloc.top_allocations(), [0; TOP_ALLOCATIONS]);
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn largest_allocation() {
+    let alloc = PeakAlloc::system();
+    let small = Layout::from_size_align(16, 8).unwrap();
+    let large = Layout::from_size_align(4_096, 8).unwrap();
+    unsafe {
+        let a = alloc.alloc(large);
+        alloc.dealloc(a, large);
+        let b = alloc.alloc(small);
+        assert_eq!(alloc.get_largest_allocation(), 4_096);
+        let b = alloc.realloc(b, small, 8_192);
+        assert_eq!(alloc.get_largest_allocation(), 8_192);
+        alloc.dealloc(b, Layout::from_size_align(8_192, 8).unwrap());
+    }
+    alloc.reset_peak_memory();
+    assert_eq!(alloc.get_largest_allocation(), 0);
+}
+
+#[test]
+fn reach_the_inner_allocator() {
+    let mock = CountingMock::new();
+    let alloc = PeakAlloc::new(&mock);
+    let layout = Layout::from_size_align(64, 8).unwrap();
+    unsafe {
+        let ptr = alloc.alloc(layout);
+        alloc.dealloc(ptr, layout);
+    }
+    assert_eq!(alloc.inner().allocs(), 1);
+    assert_eq!(alloc.inner().deallocs(), 1);
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn read_the_inner_statistics() {
+    let mock = CountingMock::new();
+    let alloc = PeakAlloc::new(&mock);
+    let layout = Layout::from_size_align(64, 8).unwrap();
+    unsafe {
+        let ptr = alloc.alloc(layout);
+        alloc.dealloc(ptr, layout);
+    }
+    let (allocs, deallocs) = alloc.with_inner_stats(|mock| (mock.allocs(), mock.deallocs()));
+    assert_eq!((allocs, deallocs), (1, 1));
+    assert_eq!(alloc.get_peak_memory(), 64);
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn suspend_tracking_on_the_current_thread() {
+    let alloc = PeakAlloc::system();
+    let layout = Layout::from_size_align(1_024, 8).unwrap();
+    let alloc_and_free = || unsafe {
+        let ptr = alloc.alloc(layout);
+        alloc.dealloc(ptr, layout);
+    };
+    {
+        let _outer = alloc.suspend();
+        {
+            let _inner = alloc.suspend();
+            alloc_and_free();
+        }
+        alloc_and_free();
+        std::thread::scope(|s| {
+            s.spawn(alloc_and_free);
+        });
+        assert_eq!(alloc.get_alloc_count(), 1);
+    }
+    alloc_and_free();
+    assert_eq!(alloc.get_alloc_count(), 2);
+    assert_eq!(alloc.get_dealloc_count(), 2);
+    assert_eq!(alloc.get_total_allocated(), 2_048);
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn top_allocations_in_descending_order() {
+    let alloc = PeakAlloc::system();
+    assert_eq!(alloc.top_allocations(), [0; TOP_ALLOCATIONS]);
+    unsafe {
+        for &size in &[100, 5_000, 300, 5_000, 1, 64, 2_048, 10, 20, 30, 40] {
+            let layout = Layout::from_size_align(size, 8).unwrap();
+            let ptr = alloc.alloc(layout);
+            alloc.dealloc(ptr, layout);
+        }
+        let layout = Layout::from_size_align(1_000, 8).unwrap();
+        let ptr = alloc.alloc(layout);
+        let ptr = alloc.realloc(ptr, layout, 8_000);
+        alloc.dealloc(ptr, Layout::from_size_align(8_000, 8).unwrap());
+    }
+    assert_eq!(
+        alloc.top_allocations(),
+        [8_000, 5_000, 5_000, 2_048, 1_000, 300, 100, 64]
+    );
+    alloc.reset_peak_memory();
+    assert_eq!(alloc.top_allocations(), [0; TOP_ALLOCATIONS]);
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn limit_rejects_allocations() {
+    let alloc = PeakAlloc::system();
+    let small = Layout::from_size_align(512, 8).unwrap();
+    alloc.set_limit(1_024);
+    unsafe {
+        let a = alloc.alloc(small);
+        assert!(!a.is_null());
+        assert!(alloc
+            .alloc(Layout::from_size_align(1_000, 8).unwrap())
+            .is_null());
+        assert!(alloc.realloc(a, small, 2_048).is_null());
+        assert_eq!(alloc.get_current_memory(), 512);
+        alloc.set_limit(0);
+        let a = alloc.realloc(a, small, 2_048);
+        assert!(!a.is_null());
+        alloc.dealloc(a, Layout::from_size_align(2_048, 8).unwrap());
+    }
+}
+
+fn fail_over_budget(_: usize) -> Admission {
+    Admission::Fail
+}
+
+fn allow_over_budget(_: usize) -> Admission {
+    Admission::Continue
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn budget_callback_decides_admission() {
+    let alloc = PeakAlloc::system()
+        .with_budget(1_024)
+        .with_budget_callback(fail_over_budget);
+    let small = Layout::from_size_align(512, 8).unwrap();
+    let large = Layout::from_size_align(1_000, 8).unwrap();
+    unsafe {
+        let a = alloc.alloc(small);
+        assert!(!a.is_null());
+        assert!(alloc.alloc(large).is_null());
+        assert!(alloc.realloc(a, small, 2_048).is_null());
+        alloc.set_budget_callback(allow_over_budget);
+        let b = alloc.alloc(large);
+        assert!(!b.is_null());
+        assert_eq!(alloc.get_current_memory(), 1_512);
+        alloc.dealloc(b, large);
+        alloc.dealloc(a, small);
+    }
+}
+
+static BUDGET_REQUESTS: AtomicUsize = AtomicUsize::new(0);
+
+fn wait_over_budget(requested: usize) -> Admission {
+    BUDGET_REQUESTS.store(requested, Ordering::SeqCst);
+    Admission::Wait
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn budget_callback_waits_for_memory_to_be_freed() {
+    let alloc = PeakAlloc::system()
+        .with_budget(1_024)
+        .with_budget_callback(wait_over_budget);
+    let held = Layout::from_size_align(1_000, 8).unwrap();
+    let small = Layout::from_size_align(100, 8).unwrap();
+    let ptr = unsafe { alloc.alloc(held) } as usize;
+    std::thread::scope(|s| {
+        let waiter = s.spawn(|| unsafe { alloc.alloc(small) } as usize);
+        while BUDGET_REQUESTS.load(Ordering::SeqCst) == 0 {
+            std::thread::yield_now();
+        }
+        assert_eq!(BUDGET_REQUESTS.load(Ordering::SeqCst), 1_100);
+        unsafe { alloc.dealloc(ptr as *mut u8, held) };
+        let ptr = waiter.join().unwrap();
+        assert_ne!(ptr, 0);
+        unsafe { alloc.dealloc(ptr as *mut u8, small) };
+    });
+    assert_eq!(alloc.get_peak_memory(), 1_000);
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn peak_growth_since_checkpoint() {
+    let alloc = PeakAlloc::system();
+    let layout = Layout::from_size_align(1_000, 8).unwrap();
+    unsafe {
+        let a = alloc.alloc(layout);
+        let first = alloc.checkpoint();
+        assert_eq!(first.peak, 1_000);
+        assert_eq!(first.current, 1_000);
+        let b = alloc.alloc(layout);
+        let second = alloc.checkpoint();
+        let c = alloc.alloc(layout);
+        assert_eq!(alloc.peak_since(&first), 2_000);
+        assert_eq!(alloc.peak_since(&second), 1_000);
+        alloc.dealloc(c, layout);
+        alloc.dealloc(b, layout);
+        alloc.dealloc(a, layout);
+    }
+    assert_eq!(alloc.peak_since(&alloc.checkpoint()), 0);
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn size_histogram_rounds_up_to_powers_of_two() {
+    let alloc = PeakAlloc::system();
+    unsafe {
+        for &size in &[1, 2, 3, 4, 5, 64, 1_000, 1_024] {
+            let layout = Layout::from_size_align(size, 1).unwrap();
+            let ptr = alloc.alloc(layout);
+            alloc.dealloc(ptr, layout);
+        }
+    }
+    let histogram = alloc.size_histogram();
+    assert_eq!(histogram[0], 1);
+    assert_eq!(histogram[1], 1);
+    assert_eq!(histogram[2], 2);
+    assert_eq!(histogram[3], 1);
+    assert_eq!(histogram[6], 1);
+    assert_eq!(histogram[10], 2);
+    assert_eq!(histogram.iter().sum::<usize>(), alloc.get_alloc_count());
+    alloc.reset_counts();
+    assert_eq!(alloc.size_histogram(), [0; HISTOGRAM_BUCKETS]);
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn alignment_histogram_by_power_of_two() {
+    let alloc = PeakAlloc::system().with_alignment_tracking();
+    assert_eq!(alloc.most_common_alignment(), None);
+    unsafe {
+        for &align in &[1, 8, 8, 64, 4_096] {
+            let layout = Layout::from_size_align(16, align).unwrap();
+            let ptr = alloc.alloc_zeroed(layout);
+            alloc.dealloc(ptr, layout);
+        }
+    }
+    let histogram = alloc.alignment_histogram();
+    assert_eq!(histogram[0], 1);
+    assert_eq!(histogram[3], 2);
+    assert_eq!(histogram[6], 1);
+    assert_eq!(histogram[12], 1);
+    assert_eq!(histogram.iter().sum::<usize>(), 5);
+    assert_eq!(alloc.most_common_alignment(), Some(8));
+    alloc.reset_counts();
+    assert!(alloc.alignment_histogram().iter().all(|&count| count == 0));
+
+    // The histogram is only tracked with the alignment tracking.
+    let untracked = PeakAlloc::system();
+    unsafe {
+        let layout = Layout::from_size_align(16, 8).unwrap();
+        let ptr = untracked.alloc(layout);
+        untracked.dealloc(ptr, layout);
+    }
+    assert_eq!(untracked.most_common_alignment(), None);
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn histogram_iter_yields_ranges_of_non_empty_buckets() {
+    let alloc = PeakAlloc::new(Dangling);
+    unsafe {
+        for &size in &[0, 3, 4, 1_000, isize::MAX as usize] {
+            let layout = Layout::from_size_align(size, 1).unwrap();
+            let ptr = alloc.alloc(layout);
+            alloc.dealloc(ptr, layout);
+        }
+    }
+    let buckets: Vec<_> = alloc.histogram_iter().collect();
+    assert_eq!(
+        buckets,
+        vec![
+            (0..2, 1),
+            (3..5, 2),
+            (513..1_025, 1),
+            ((1 << 30) + 1..usize::MAX, 1),
+        ]
+    );
+    assert!(buckets
+        .iter()
+        .all(|(range, _)| range.contains(&range.start)));
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn histogram_summary_approximates_sizes() {
+    let alloc = PeakAlloc::new(Dangling);
+    assert_eq!(alloc.histogram_summary().count, 0);
+    assert_eq!(alloc.histogram_summary().median, 0);
+    unsafe {
+        for size in (0..100).map(|i| if i < 90 { 48 } else { 3_000 }) {
+            let layout = Layout::from_size_align(size, 1).unwrap();
+            let ptr = alloc.alloc(layout);
+            alloc.dealloc(ptr, layout);
+        }
+    }
+    let summary = alloc.histogram_summary();
+    assert_eq!(summary.count, 100);
+    assert_eq!(summary.median, 64);
+    assert_eq!(summary.p90, 64);
+    assert_eq!(summary.p99, 4_096);
+    assert_eq!(summary.mean, (90.0 * 64.0 + 10.0 * 4_096.0) / 100.0);
+}
+
+fn checked_peak(alloc: &PeakAlloc<System>, fail: bool) -> Result<usize, ()> {
+    let layout = Layout::from_size_align(4_096, 8).unwrap();
+    Ok(measure_peak!(alloc, {
+        unsafe {
+            let ptr = alloc.alloc(layout);
+            alloc.dealloc(ptr, layout);
+        }
+        if fail {
+            Err(())?;
+        }
+    }))
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn measure_peak_macro() {
+    let alloc = PeakAlloc::system();
+    assert_eq!(checked_peak(&alloc, false), Ok(4_096));
+    assert_eq!(checked_peak(&alloc, true), Err(()));
+    let layout = Layout::from_size_align(100, 8).unwrap();
+    let (ptr, peak) = measure_with_peak!(&alloc, { unsafe { alloc.alloc(layout) } });
+    assert_eq!(peak, 100);
+    unsafe { alloc.dealloc(ptr, layout) };
+}
+
+/// Fails every allocation, like an allocator which is out of memory.
+struct Exhausted;
+
+unsafe impl GlobalAlloc for Exhausted {
+    unsafe fn alloc(&self, _layout: Layout) -> *mut u8 {
+        std::ptr::null_mut()
+    }
+
+    unsafe fn dealloc(&self, _ptr: *mut u8, _layout: Layout) {}
+}
+
+#[test]
+fn failed_allocations_are_not_tracked() {
+    let alloc = PeakAlloc::new(Exhausted);
+    let layout = Layout::from_size_align(1_024, 8).unwrap();
+    unsafe {
+        assert!(alloc.alloc(layout).is_null());
+        assert!(alloc.alloc_zeroed(layout).is_null());
+    }
+    assert_eq!(alloc.snapshot(), PeakAlloc::new(Exhausted).snapshot());
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn failed_realloc_keeps_the_original_block_tracked() {
+    let mock = CountingMock::new();
+    let alloc = PeakAlloc::new(&mock);
+    let layout = Layout::from_size_align(1_024, 8).unwrap();
+    unsafe {
+        let ptr = alloc.alloc(layout);
+        let before = alloc.clone_stats();
+        mock.set_failing(true);
+        assert!(alloc.realloc(ptr, layout, 4_096).is_null());
+        assert_eq!(alloc.clone_stats(), before);
+        assert_eq!(alloc.get_current_memory(), 1_024);
+        mock.set_failing(false);
+        alloc.dealloc(ptr, layout);
+    }
+    assert_eq!(alloc.get_current_memory(), 0);
+    assert_eq!(alloc.get_peak_memory(), 1_024);
+}
+
+fn grow_and_shrink<A: GlobalAlloc>(alloc: &A) {
+    let layout = Layout::from_size_align(1_024, 8).unwrap();
+    unsafe {
+        let ptr = alloc.alloc(layout);
+        let ptr = alloc.realloc(ptr, layout, 8_192);
+        let ptr = alloc.realloc(ptr, Layout::from_size_align(8_192, 8).unwrap(), 512);
+        alloc.dealloc(ptr, Layout::from_size_align(512, 8).unwrap());
+    }
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn reference_and_owned_realloc_tracking_match() {
+    let owned = PeakAlloc::system();
+    grow_and_shrink(&owned);
+    let behind_ref = PeakAlloc::system();
+    let by_ref: &PeakAlloc<System> = &behind_ref;
+    grow_and_shrink(&by_ref);
+    for stats in [owned.clone_stats(), behind_ref.clone_stats()] {
+        assert_eq!(stats.peak, 8_192);
+        assert_eq!(stats.current, 0);
+        assert_eq!(stats.total_allocated, 8_192);
+        assert_eq!(stats.total_deallocated, 8_192);
+        assert_eq!(stats.realloc_growth_bytes, 7_168);
+        assert_eq!(stats.realloc_moved_count + stats.realloc_inplace_count, 2);
+    }
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn alignment_overhead() {
+    let alloc = PeakAlloc::system().with_alignment_tracking();
+    let over_aligned = Layout::from_size_align(10, 64).unwrap();
+    let aligned = Layout::from_size_align(64, 64).unwrap();
+    unsafe {
+        let a = alloc.alloc(over_aligned);
+        let b = alloc.alloc(aligned);
+        assert_eq!(alloc.get_alignment_overhead(), 54);
+        let a = alloc.realloc(a, over_aligned, 100);
+        assert_eq!(alloc.get_alignment_overhead(), 28);
+        alloc.dealloc(a, Layout::from_size_align(100, 64).unwrap());
+        alloc.dealloc(b, aligned);
+    }
+    assert_eq!(alloc.get_alignment_overhead(), 0);
+    assert_eq!(PeakAlloc::system().get_alignment_overhead(), 0);
+}
+
+/// Resizes in place as long as the block fits into its 1 KiB slot, and moves it
+/// to the next slot otherwise.
+struct Slots(*mut u8);
+
+unsafe impl GlobalAlloc for Slots {
+    unsafe fn alloc(&self, _layout: Layout) -> *mut u8 {
+        self.0
+    }
+
+    unsafe fn dealloc(&self, _ptr: *mut u8, _layout: Layout) {}
+
+    unsafe fn realloc(&self, ptr: *mut u8, _layout: Layout, new_size: usize) -> *mut u8 {
+        if new_size <= 1_024 {
+            ptr
+        } else {
+            ptr.add(1_024)
+        }
+    }
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn wrap_allocators_without_default() {
+    let wrapped: PeakAlloc<Slots> = PeakAlloc::new(Slots(8 as *mut u8));
+    let relaxed = PeakAlloc::relaxed(Slots(16 as *mut u8));
+    let layout = Layout::from_size_align(100, 8).unwrap();
+    unsafe {
+        assert_eq!(wrapped.alloc(layout) as usize, 8);
+        assert_eq!(relaxed.alloc(layout) as usize, 16);
+    }
+    assert_eq!(wrapped.get_current_memory(), 100);
+    assert_eq!(relaxed.get_current_memory(), 100);
+    assert_eq!(PeakAlloc::<System>::default().get_peak_memory(), 0);
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn count_moved_and_inplace_reallocations() {
+    let alloc = PeakAlloc::new(Slots(8 as *mut u8));
+    let layout = Layout::from_size_align(100, 8).unwrap();
+    unsafe {
+        let ptr = alloc.alloc(layout);
+        let ptr = alloc.realloc(ptr, layout, 1_000);
+        let ptr = alloc.realloc(ptr, Layout::from_size_align(1_000, 8).unwrap(), 2_000);
+        alloc.dealloc(ptr, Layout::from_size_align(2_000, 8).unwrap());
+    }
+    assert_eq!(alloc.get_realloc_inplace_count(), 1);
+    assert_eq!(alloc.get_realloc_moved_count(), 1);
+    assert_eq!(alloc.get_realloc_copied_bytes(), 1_000);
+    alloc.reset_counts();
+    assert_eq!(alloc.get_realloc_moved_count(), 0);
+    assert_eq!(alloc.get_realloc_copied_bytes(), 0);
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn named_regions_report_their_peaks() {
+    let alloc = PeakAlloc::system();
+    let small = Layout::from_size_align(1_024, 8).unwrap();
+    let large = Layout::from_size_align(4_096, 8).unwrap();
+    unsafe {
+        let outer = alloc.alloc(small);
+        alloc.push_region("parsing");
+        let ptr = alloc.alloc(large);
+        alloc.dealloc(ptr, large);
+        alloc.push_region("lexing");
+        let ptr = alloc.alloc(small);
+        alloc.dealloc(ptr, small);
+        assert_eq!(alloc.pop_region(), Some(1_024));
+        assert_eq!(alloc.pop_region(), Some(4_096));
+        alloc.dealloc(outer, small);
+
+        alloc.push_region("lexing");
+        let ptr = alloc.alloc(large);
+        alloc.dealloc(ptr, large);
+        assert_eq!(alloc.pop_region(), Some(4_096));
+    }
+    assert_eq!(alloc.pop_region(), None);
+    assert_eq!(
+        alloc.region_report(),
+        vec![("lexing", 4_096), ("parsing", 4_096)]
+    );
+}
+
+#[test]
+fn named_regions_are_separate_per_allocator() {
+    let first = PeakAlloc::system();
+    let second = PeakAlloc::system();
+    first.push_region("first");
+    second.push_region("second");
+    assert_eq!(first.pop_region(), Some(0));
+    assert_eq!(first.pop_region(), None);
+    assert_eq!(second.pop_region(), Some(0));
+    assert_eq!(second.region_report(), vec![("second", 0)]);
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn sampler_records_memory_over_time() {
+    static SAMPLED: PeakAlloc<System> = PeakAlloc::system();
+    let layout = Layout::from_size_align(4_096, 8).unwrap();
+    let ptr = unsafe { SAMPLED.alloc(layout) };
+    let sampler = SAMPLED.spawn_sampler(Duration::from_millis(1));
+    std::thread::sleep(Duration::from_millis(20));
+    let samples = sampler.stop();
+    unsafe { SAMPLED.dealloc(ptr, layout) };
+
+    assert!(samples.len() >= 2);
+    assert!(samples.iter().all(|&(_, current)| current == 4_096));
+    assert!(samples.windows(2).all(|pair| pair[0].0 <= pair[1].0));
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn sampler_forgets_peaks_outside_the_window() {
+    static WINDOWED: PeakAlloc<System> = PeakAlloc::system();
+    let layout = Layout::from_size_align(1 << 20, 8).unwrap();
+    let ptr = unsafe { WINDOWED.alloc(layout) };
+    let sampler = WINDOWED.spawn_sampler(Duration::from_millis(1));
+    std::thread::sleep(Duration::from_millis(20));
+    assert_eq!(sampler.recent_peak(), 1 << 20);
+    unsafe { WINDOWED.dealloc(ptr, layout) };
+
+    sampler.set_peak_window(Duration::from_millis(10));
+    std::thread::sleep(Duration::from_millis(50));
+    assert_eq!(sampler.recent_peak(), 0);
+    assert_eq!(WINDOWED.get_peak_memory(), 1 << 20);
+    // The samples which left the window are dropped.
+    let samples = sampler.stop();
+    assert!(samples.iter().all(|&(_, current)| current == 0));
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn sampler_measures_the_allocation_rate() {
+    static BURST: PeakAlloc<System> = PeakAlloc::system();
+    let sampler = BURST.spawn_sampler(Duration::from_millis(1));
+    std::thread::sleep(Duration::from_millis(10));
+    assert_eq!(sampler.peak_allocation_rate(), 0.0);
+    let layout = Layout::from_size_align(1 << 20, 8).unwrap();
+    unsafe {
+        let ptr = BURST.alloc(layout);
+        BURST.dealloc(ptr, layout);
+    }
+    std::thread::sleep(Duration::from_millis(10));
+    let rate = sampler.peak_allocation_rate();
+    let samples = sampler.stop();
+    let elapsed = samples.last().unwrap().0 - samples.first().unwrap().0;
+    // The whole burst falls between two of the samples.
+    assert!(rate >= (1 << 20) as f64 / elapsed.as_secs_f64());
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn nested_instrumenting_allocators() {
+    let layout = Layout::from_size_align(1_024, 8).unwrap();
+    let grown = Layout::from_size_align(4_096, 8).unwrap();
+    let inner = PeakAlloc::system();
+    let outer = PeakAlloc::new(&inner);
+    unsafe {
+        let direct = inner.alloc(layout);
+        let ptr = outer.alloc(layout);
+        let ptr = outer.realloc(ptr, layout, 4_096);
+        outer.dealloc(ptr, grown);
+        inner.dealloc(direct, layout);
+    }
+    assert_eq!(outer.get_peak_memory(), 4_096);
+    assert_eq!(outer.get_alloc_count(), 1);
+    // The inner layer additionally sees the memory allocated around the outer
+    // one.
+    assert_eq!(inner.get_peak_memory(), 5_120);
+    assert_eq!(inner.get_alloc_count(), 2);
+    assert_eq!(inner.get_dealloc_count(), 2);
+
+    let owned: PeakAlloc<PeakAlloc<System>> = PeakAlloc::new(PeakAlloc::system());
+    unsafe {
+        let ptr = owned.alloc(layout);
+        owned.dealloc(ptr, layout);
+    }
+    assert_eq!(owned.get_peak_memory(), 1_024);
+    assert!(format!("{:?}", owned).starts_with("PeakAlloc"));
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn concurrent_peak_matches_true_peak() {
+    const THREADS: usize = 8;
+    const HELD: usize = 4_096;
+    let alloc = PeakAlloc::new(Dangling);
+    let barrier = Barrier::new(THREADS);
+    std::thread::scope(|scope| {
+        for _ in 0..THREADS {
+            scope.spawn(|| unsafe {
+                // Each thread holds at most `HELD` bytes while churning, ...
+                for size in (1..=HELD).step_by(7) {
+                    let layout = Layout::from_size_align(size, 1).unwrap();
+                    let ptr = alloc.alloc(layout);
+                    alloc.dealloc(ptr, layout);
+                }
+                // ... and exactly `HELD` bytes while all threads wait here.
+                let layout = Layout::from_size_align(HELD, 1).unwrap();
+                let ptr = alloc.alloc(layout);
+                barrier.wait();
+                alloc.dealloc(ptr, layout);
+            });
+        }
+    });
+    assert_eq!(alloc.get_current_memory(), 0);
+    assert_eq!(alloc.get_peak_memory(), THREADS * HELD);
+}
+
+#[test]
+fn sharded_frees_on_other_threads_return_to_zero() {
+    const THREADS: usize = 8;
+    let alloc = PeakAlloc::new(Dangling).with_sharding();
+    // Below the flush threshold, so the allocations stay in the shards.
+    let layout = Layout::from_size_align(40 * 1024, 8).unwrap();
+    let barrier = Barrier::new(THREADS + 1);
+    let ptrs = std::sync::Mutex::new(Vec::new());
+    std::thread::scope(|scope| {
+        for _ in 0..THREADS {
+            scope.spawn(|| {
+                let ptr = unsafe { alloc.alloc(layout) };
+                ptrs.lock().unwrap().push(ptr as usize);
+                // Keeps the threads, and so their stacks, alive until all of
+                // them allocated.
+                barrier.wait();
+            });
+        }
+        barrier.wait();
+        for ptr in ptrs.lock().unwrap().drain(..) {
+            unsafe { alloc.dealloc(ptr as *mut u8, layout) };
+        }
+    });
+    assert_eq!(alloc.get_current_memory(), 0);
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn counting_mock_sees_every_call() {
+    let mock = CountingMock::new();
+    let alloc = PeakAlloc::new(&mock);
+    let layout = Layout::from_size_align(512, 8).unwrap();
+    unsafe {
+        let ptr = alloc.alloc_zeroed(layout);
+        let ptr = alloc.realloc(ptr, layout, 1_024);
+        mock.set_failing(true);
+        assert!(alloc.alloc(layout).is_null());
+        let grown = Layout::from_size_align(1_024, 8).unwrap();
+        assert!(alloc.realloc(ptr, grown, 4_096).is_null());
+        mock.set_failing(false);
+        alloc.dealloc(ptr, grown);
+    }
+    assert_eq!((mock.allocs(), mock.reallocs(), mock.deallocs()), (2, 2, 1));
+    assert_eq!(alloc.get_alloc_count(), 1);
+    assert_eq!(alloc.get_dealloc_count(), 1);
+    assert_eq!(alloc.get_peak_memory(), 1_024);
+    assert_eq!(alloc.get_current_memory(), 0);
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn local_allocator_tracks_without_atomics() {
+    // Safety: the allocator is only used by this thread.
+    let alloc = unsafe { PeakAllocLocal::new(System) };
+    let layout = Layout::from_size_align(1_024, 8).unwrap();
+    unsafe {
+        let a = alloc.alloc(layout);
+        let b = alloc.alloc_zeroed(layout);
+        let b = alloc.realloc(b, layout, 4_096);
+        assert_eq!(alloc.get_current_memory(), 5_120);
+        alloc.dealloc(b, Layout::from_size_align(4_096, 8).unwrap());
+        alloc.reset_peak_memory();
+        assert_eq!(alloc.get_peak_memory(), 1_024);
+        alloc.dealloc(a, layout);
+    }
+    assert_eq!(alloc.get_current_memory(), 0);
+    assert_eq!(alloc.get_total_allocated(), 0);
+    assert_eq!(alloc.get_alloc_count(), 2);
+    assert_eq!(alloc.get_dealloc_count(), 2);
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn log_every_flags_new_peaks() {
+    let alloc = PeakAlloc::system();
+    alloc.log_every(2);
+    let layout = Layout::from_size_align(64, 8).unwrap();
+    unsafe {
+        let first = alloc.alloc(layout);
+        assert_eq!(alloc.take_logged_peak(), None);
+        let second = alloc.alloc(layout);
+        assert_eq!(alloc.take_logged_peak(), Some(128));
+        assert_eq!(alloc.take_logged_peak(), None);
+
+        // The peak didn't grow since the last check.
+        alloc.dealloc(second, layout);
+        alloc.dealloc(first, layout);
+        let first = alloc.alloc(layout);
+        let second = alloc.alloc(layout);
+        assert_eq!(alloc.take_logged_peak(), None);
+        alloc.dealloc(second, layout);
+        alloc.dealloc(first, layout);
+
+        // A pending peak is dropped by `reset_all`.
+        let large = Layout::from_size_align(1_024, 8).unwrap();
+        let first = alloc.alloc(large);
+        let second = alloc.alloc(large);
+        alloc.reset_all();
+        assert_eq!(alloc.take_logged_peak(), None);
+        alloc.dealloc(second, large);
+        alloc.dealloc(first, large);
+
+        alloc.log_every(0);
+        let first = alloc.alloc(large);
+        let second = alloc.alloc(large);
+        assert_eq!(alloc.take_logged_peak(), None);
+        alloc.dealloc(second, large);
+        alloc.dealloc(first, large);
+    }
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn last_alloc_was_peak() {
+    let alloc = PeakAlloc::system();
+    assert!(!alloc.last_alloc_was_peak());
+    let layout = Layout::from_size_align(64, 8).unwrap();
+    unsafe {
+        let first = alloc.alloc(layout);
+        assert!(alloc.last_alloc_was_peak());
+        alloc.dealloc(first, layout);
+        let first = alloc.alloc(layout);
+        assert!(!alloc.last_alloc_was_peak());
+        let first = alloc.realloc(first, layout, 128);
+        assert!(alloc.last_alloc_was_peak());
+        alloc.dealloc(first, Layout::from_size_align(128, 8).unwrap());
+    }
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn threshold_predicates() {
+    let alloc = PeakAlloc::system();
+    let layout = Layout::from_size_align(1_024, 8).unwrap();
+    unsafe {
+        let ptr = alloc.alloc(layout);
+        assert!(alloc.current_exceeded(1_023));
+        assert!(!alloc.current_exceeded(1_024));
+        assert_eq!(alloc.remaining_until(4_096), 3_072);
+        assert_eq!(alloc.remaining_until(512), 0);
+        alloc.dealloc(ptr, layout);
+    }
+    assert!(!alloc.current_exceeded(0));
+    assert!(alloc.peak_exceeded(1_000));
+    assert!(!alloc.peak_exceeded(1_024));
+    assert_eq!(alloc.remaining_until(4_096), 4_096);
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn peak_by_size_class() {
+    let alloc = PeakAlloc::system().with_size_class_tracking();
+    let small = Layout::from_size_align(16, 8).unwrap();
+    let large = Layout::from_size_align(4_096, 8).unwrap();
+    unsafe {
+        let blocks: Vec<_> = (0..4).map(|_| alloc.alloc(small)).collect();
+        for &block in &blocks {
+            alloc.dealloc(block, small);
+        }
+        let block = alloc.alloc(large);
+        // Moves the block from the class of 4 KiB to the one of 8 KiB.
+        let block = alloc.realloc(block, large, 8_192);
+        alloc.dealloc(block, Layout::from_size_align(8_192, 8).unwrap());
+    }
+    let peaks = alloc.peak_by_size_class();
+    assert_eq!(peaks[4], 64);
+    assert_eq!(peaks[12], 4_096);
+    assert_eq!(peaks[13], 8_192);
+    assert_eq!(peaks.iter().sum::<usize>(), 64 + 4_096 + 8_192);
+
+    alloc.reset_peak_memory();
+    assert_eq!(alloc.peak_by_size_class(), [0; HISTOGRAM_BUCKETS]);
+
+    // Size classes are opt-in.
+    let untracked = PeakAlloc::system();
+    unsafe {
+        let block = untracked.alloc(large);
+        untracked.dealloc(block, large);
+    }
+    assert_eq!(untracked.peak_by_size_class(), [0; HISTOGRAM_BUCKETS]);
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn wrap_an_allocator_chosen_at_runtime() {
+    static MOCK: CountingMock = CountingMock::new();
+    let inner: Box<dyn GlobalAlloc + Send + Sync> = if MOCK.allocs() == 0 {
+        Box::new(&MOCK)
+    } else {
+        Box::new(System)
+    };
+    let alloc = PeakAlloc::new(DynAlloc::from(inner));
+    let layout = Layout::from_size_align(64, 8).unwrap();
+    unsafe {
+        let ptr = alloc.alloc_zeroed(layout);
+        let ptr = alloc.realloc(ptr, layout, 128);
+        alloc.dealloc(ptr, Layout::from_size_align(128, 8).unwrap());
+    }
+    assert_eq!(alloc.get_peak_memory(), 128);
+    assert_eq!(MOCK.allocs(), 1);
+    assert_eq!(MOCK.reallocs(), 1);
+    assert_eq!(MOCK.deallocs(), 1);
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn memory_limit_reached() {
+    let mock = CountingMock::new();
+    let alloc = PeakAlloc::new(&mock);
+    let layout = Layout::from_size_align(1_024, 8).unwrap();
+    unsafe {
+        mock.set_failing(true);
+        assert!(alloc.alloc(layout).is_null());
+        assert!(!alloc.memory_limit_reached());
+        mock.set_failing(false);
+
+        alloc.set_limit(512);
+        assert!(alloc.alloc(layout).is_null());
+        assert!(alloc.memory_limit_reached());
+        alloc.set_limit(0);
+        let ptr = alloc.alloc(layout);
+        assert!(alloc.memory_limit_reached());
+        alloc.dealloc(ptr, layout);
+    }
+    alloc.clear_memory_limit_reached();
+    assert!(!alloc.memory_limit_reached());
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn peak_above_the_baseline() {
+    let alloc = PeakAlloc::system();
+    let runtime = Layout::from_size_align(512, 8).unwrap();
+    let program = Layout::from_size_align(2_048, 8).unwrap();
+    unsafe {
+        let overhead = alloc.alloc(runtime);
+        assert_eq!(alloc.baseline(), 512);
+        let ptr = alloc.alloc(program);
+        assert_eq!(alloc.baseline(), 512);
+        alloc.dealloc(ptr, program);
+        assert_eq!(alloc.peak_above_baseline(), 2_048);
+        alloc.reset_all();
+        assert_eq!(alloc.baseline(), 512);
+        assert_eq!(alloc.peak_above_baseline(), 0);
+        alloc.dealloc(overhead, runtime);
+    }
+}
+
+#[test]
+#[cfg(not(feature = "disabled"))]
+fn peak_observer_runs_on_publish() {
+    let alloc = PeakAlloc::system();
+    let (sender, peaks) = std::sync::mpsc::channel();
+    let sender = std::sync::Mutex::new(sender);
+    alloc.set_peak_observer(Box::new(move |peak| {
+        sender.lock().unwrap().send(peak).unwrap();
+    }));
+    let layout = Layout::from_size_align(1_024, 8).unwrap();
+    unsafe {
+        let ptr = alloc.alloc(layout);
+        alloc.dealloc(ptr, layout);
+    }
+    assert!(peaks.try_recv().is_err());
+    alloc.publish();
+    assert_eq!(peaks.try_recv(), Ok(1_024));
+    // The peak didn't grow since.
+    alloc.publish();
+    assert!(peaks.try_recv().is_err());
+
+    alloc.clear_peak_observer();
+    unsafe {
+        let ptr = alloc.alloc(Layout::from_size_align(4_096, 8).unwrap());
+        alloc.dealloc(ptr, Layout::from_size_align(4_096, 8).unwrap());
+    }
+    alloc.publish();
+    assert!(peaks.try_recv().is_err());
+
+    // A peak published without an observer is reported to the next one.
+    let (sender, peaks) = std::sync::mpsc::channel();
+    let sender = std::sync::Mutex::new(sender);
+    alloc.set_peak_observer(Box::new(move |peak| {
+        sender.lock().unwrap().send(peak).unwrap();
+    }));
+    alloc.publish();
+    assert_eq!(peaks.try_recv(), Ok(4_096));
+}
@@ -0,0 +1,53 @@
+#![cfg(all(feature = "testing", not(feature = "disabled")))]
+
+extern crate peakmem_alloc;
+
+use peakmem_alloc::PeakAlloc;
+use std::alloc::{GlobalAlloc, Layout};
+
+fn peak_of(bytes: usize) -> PeakAlloc<std::alloc::System> {
+    let alloc = PeakAlloc::system();
+    let layout = Layout::from_size_align(bytes, 8).unwrap();
+    unsafe {
+        let ptr = alloc.alloc(layout);
+        alloc.dealloc(ptr, layout);
+    }
+    alloc
+}
+
+#[test]
+fn peak_below_the_limit() {
+    peak_of(1_024).assert_peak_below(1_025);
+}
+
+#[test]
+#[should_panic(
+    expected = "peak memory consumption of 1.50 KiB (1536 bytes) is not below the limit of \
+                1.00 KiB (1024 bytes)"
+)]
+fn peak_above_the_limit() {
+    peak_of(1_536).assert_peak_below(1_024);
+}
+
+#[test]
+fn peak_within_the_tolerance() {
+    let alloc = peak_of(1_000);
+    alloc.assert_peak_eq_within(1_024, 24);
+    alloc.assert_peak_eq_within(990, 10);
+}
+
+#[test]
+#[should_panic(
+    expected = "peak memory consumption of 2.00 KiB (2048 bytes) differs from the expected \
+                1.00 KiB (1024 bytes) by +1.00 KiB (+1024 bytes), more than the tolerance of \
+                512 B (512 bytes)"
+)]
+fn peak_above_the_tolerance() {
+    peak_of(2_048).assert_peak_eq_within(1_024, 512);
+}
+
+#[test]
+#[should_panic(expected = "by -1.00 KiB (-1024 bytes)")]
+fn peak_below_the_tolerance() {
+    peak_of(1_024).assert_peak_eq_within(2_048, 0);
+}
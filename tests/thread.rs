@@ -0,0 +1,71 @@
+#![cfg(all(feature = "std", not(feature = "disabled")))]
+
+extern crate peakmem_alloc;
+
+use peakmem_alloc::ThreadPeakAlloc;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::Barrier;
+use std::thread;
+
+fn alloc_and_free(alloc: &ThreadPeakAlloc<System>, size: usize) -> usize {
+    let layout = Layout::from_size_align(size, 8).unwrap();
+    unsafe {
+        let ptr = alloc.alloc(layout);
+        alloc.dealloc(ptr, layout);
+    }
+    alloc.get_thread_peak()
+}
+
+#[test]
+fn threads_report_their_own_peak() {
+    let alloc = ThreadPeakAlloc::system();
+    let (a, b) = thread::scope(|s| {
+        let a = s.spawn(|| alloc_and_free(&alloc, 1_000));
+        let b = s.spawn(|| alloc_and_free(&alloc, 300));
+        (a.join().unwrap(), b.join().unwrap())
+    });
+    assert_eq!(a, 1_000);
+    assert_eq!(b, 300);
+    assert_eq!(alloc.global().get_total_allocated(), 1_300);
+}
+
+#[test]
+fn reset_thread_peak() {
+    let alloc = ThreadPeakAlloc::system();
+    let layout = Layout::from_size_align(100, 8).unwrap();
+    unsafe {
+        let ptr = alloc.alloc(layout);
+        alloc.reset_thread_peak();
+        assert_eq!(alloc.get_thread_peak(), 100);
+        let ptr = alloc.realloc(ptr, layout, 50);
+        assert_eq!(alloc.get_thread_current(), 50);
+        alloc.dealloc(ptr, Layout::from_size_align(50, 8).unwrap());
+    }
+    alloc.reset_thread_peak();
+    assert_eq!(alloc.get_thread_peak(), 0);
+}
+
+#[test]
+fn peak_breakdown_per_thread() {
+    let alloc = ThreadPeakAlloc::system();
+    let barrier = Barrier::new(2);
+    let hold = |size: usize| {
+        let layout = Layout::from_size_align(size, 8).unwrap();
+        unsafe {
+            let ptr = alloc.alloc(layout);
+            barrier.wait();
+            barrier.wait();
+            alloc.dealloc(ptr, layout);
+        }
+        thread::current().id()
+    };
+    let (a, b) = thread::scope(|s| {
+        let a = s.spawn(|| hold(1_000));
+        let b = s.spawn(|| hold(300));
+        (a.join().unwrap(), b.join().unwrap())
+    });
+    assert_eq!(alloc.global().get_peak_memory(), 1_300);
+    let mut breakdown = alloc.peak_breakdown();
+    breakdown.sort_by_key(|&(_, bytes)| bytes);
+    assert_eq!(breakdown, vec![(b, 300), (a, 1_000)]);
+}
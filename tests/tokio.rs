@@ -0,0 +1,40 @@
+#![cfg(all(feature = "tokio", not(feature = "disabled")))]
+
+extern crate peakmem_alloc;
+extern crate tokio;
+
+use peakmem_alloc::PeakAlloc;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::future::poll_fn;
+use std::task::Poll;
+
+const HELD: usize = 1_000;
+
+#[test]
+fn task_memory_is_relative_to_the_task_baseline() {
+    static ALLOC: PeakAlloc<System> = PeakAlloc::system();
+    let layout = Layout::from_size_align(HELD, 8).unwrap();
+    let before = unsafe { ALLOC.alloc(layout) };
+    assert_eq!(ALLOC.task_memory(), None);
+
+    let mut held = None;
+    let task = ALLOC.measure_tokio_task(poll_fn(move |cx| match held.take() {
+        None => {
+            assert_eq!(ALLOC.task_memory(), Some(0));
+            held = Some(unsafe { ALLOC.alloc(layout) });
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+        Some(ptr) => {
+            assert_eq!(ALLOC.task_memory(), Some(HELD));
+            unsafe { ALLOC.dealloc(ptr, layout) };
+            Poll::Ready(42)
+        }
+    }));
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    assert_eq!(runtime.block_on(task), (42, HELD));
+
+    unsafe { ALLOC.dealloc(before, layout) };
+}
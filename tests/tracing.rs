@@ -0,0 +1,92 @@
+#![cfg(all(feature = "tracing", not(feature = "disabled")))]
+
+extern crate peakmem_alloc;
+extern crate tracing;
+
+use peakmem_alloc::PeakAlloc;
+use std::alloc::{GlobalAlloc, Layout};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+
+#[derive(Default)]
+struct PeakEvents {
+    count: AtomicU64,
+    peak: AtomicU64,
+    delta: AtomicU64,
+}
+
+impl Visit for &PeakEvents {
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        match field.name() {
+            "peak" => self.peak.store(value, Ordering::SeqCst),
+            "delta" => self.delta.store(value, Ordering::SeqCst),
+            _ => {}
+        }
+    }
+
+    fn record_debug(&mut self, _field: &Field, _value: &dyn fmt::Debug) {}
+}
+
+struct Recorder(Arc<PeakEvents>);
+
+impl Subscriber for Recorder {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        metadata.target() == "peakmem_alloc"
+    }
+
+    fn new_span(&self, _span: &Attributes<'_>) -> Id {
+        Id::from_u64(1)
+    }
+
+    fn record(&self, _span: &Id, _values: &Record<'_>) {}
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        self.0.count.fetch_add(1, Ordering::SeqCst);
+        event.record(&mut &*self.0);
+    }
+
+    fn enter(&self, _span: &Id) {}
+
+    fn exit(&self, _span: &Id) {}
+}
+
+#[test]
+fn events_respect_peak_threshold() {
+    let events = Arc::new(PeakEvents::default());
+    let alloc = PeakAlloc::system();
+    alloc.set_peak_threshold(1_000);
+    let small = Layout::from_size_align(600, 8).unwrap();
+    tracing::subscriber::with_default(Recorder(events.clone()), || unsafe {
+        let a = alloc.alloc(small);
+        assert_eq!(events.count.load(Ordering::SeqCst), 0);
+        let b = alloc.alloc(small);
+        assert_eq!(events.count.load(Ordering::SeqCst), 1);
+        assert_eq!(events.peak.load(Ordering::SeqCst), 1_200);
+        assert_eq!(events.delta.load(Ordering::SeqCst), 1_200);
+        let c = alloc.alloc(small);
+        assert_eq!(events.count.load(Ordering::SeqCst), 1);
+        alloc.dealloc(c, small);
+        alloc.dealloc(b, small);
+        alloc.dealloc(a, small);
+    });
+}
+
+#[test]
+fn publish_emits_an_event() {
+    let events = Arc::new(PeakEvents::default());
+    let alloc = PeakAlloc::system();
+    let layout = Layout::from_size_align(256, 8).unwrap();
+    unsafe {
+        let ptr = alloc.alloc(layout);
+        alloc.dealloc(ptr, layout);
+    }
+    tracing::subscriber::with_default(Recorder(events.clone()), || alloc.publish());
+    assert_eq!(events.count.load(Ordering::SeqCst), 1);
+    assert_eq!(events.peak.load(Ordering::SeqCst), 256);
+}